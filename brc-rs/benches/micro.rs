@@ -0,0 +1,93 @@
+//! The hot inner pieces of the current solver, so regressions show up without
+//! a full run.
+
+use std::hint::black_box;
+
+use brc_rs::{
+  generate::push_temperature,
+  profile::{generate_profile, Profile},
+  solve_v15::{fnv_hash, key_words, parse_temperature_at, FixedSizeMap},
+};
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+
+/// every temperature from -99.9 to 99.9, one per line
+fn temperatures() -> (Vec<u8>, Vec<usize>) {
+  let mut buffer = Vec::new();
+  let mut starts = Vec::new();
+  for tenths in -999..=999 {
+    starts.push(buffer.len());
+    push_temperature(&mut buffer, tenths);
+    buffer.push(b'\n');
+  }
+  (buffer, starts)
+}
+
+/// the station names of a generated input, in file order
+fn names(profile: Profile, rows: u64) -> Vec<Vec<u8>> {
+  generate_profile(profile, 10_000, rows, 1)
+    .split(|&b| b == b'\n')
+    .filter(|line| !line.is_empty())
+    .map(|line| line[..line.iter().rposition(|&b| b == b';').unwrap()].to_vec())
+    .collect()
+}
+
+fn parse_temperature(c: &mut Criterion) {
+  let (buffer, starts) = temperatures();
+  let mut group = c.benchmark_group("parse_temperature");
+  group.throughput(Throughput::Elements(starts.len() as u64));
+  group.bench_function("all values", |b| {
+    b.iter(|| {
+      let mut total = 0;
+      for &start in &starts {
+        total += parse_temperature_at(black_box(&buffer), start).0;
+      }
+      total
+    })
+  });
+  group.finish();
+}
+
+fn hash(c: &mut Criterion) {
+  let mut group = c.benchmark_group("fnv_hash");
+  for profile in [Profile::Weather, Profile::SharedPrefixes] {
+    let names = names(profile, 10_000);
+    let words: Vec<_> = names.iter().map(|name| (key_words(name)[0], name.len())).collect();
+    group.throughput(Throughput::Elements(words.len() as u64));
+    group.bench_function(format!("{:?}", profile), |b| {
+      b.iter(|| {
+        words
+          .iter()
+          .fold(0, |acc, &(word, len)| acc ^ fnv_hash(black_box(word), len))
+      })
+    });
+  }
+  group.finish();
+}
+
+fn get_or_insert(c: &mut Criterion) {
+  let mut group = c.benchmark_group("FixedSizeMap::get_or_insert");
+  // names sharing their first word all probe the same run of slots, slow enough
+  // that the defaults would take minutes
+  group.sample_size(10);
+  for profile in [Profile::Weather, Profile::SharedPrefixes, Profile::MaxLengthNames] {
+    let names = names(profile, 10_000);
+    let keys: Vec<_> = names.iter().map(|name| (name.as_slice(), key_words(name))).collect();
+    let mut map = FixedSizeMap::new();
+    // only look ups of names that are already in the map, like most rows
+    for &(name, words) in &keys {
+      map.get_or_insert(name, words);
+    }
+    group.throughput(Throughput::Elements(keys.len() as u64));
+    group.bench_function(format!("{:?}", profile), |b| {
+      b.iter(|| {
+        keys
+          .iter()
+          .fold(0, |acc, &(name, words)| acc ^ map.get_or_insert(black_box(name), words))
+      })
+    });
+  }
+  group.finish();
+}
+
+criterion_group!(benches, parse_temperature, hash, get_or_insert);
+criterion_main!(benches);
@@ -0,0 +1,59 @@
+//! Every solver end to end on generated inputs, small enough that the slow
+//! early versions still finish in reasonable time.
+
+use std::{fs, path::PathBuf};
+
+use brc_rs::{
+  profile::{generate_profile, Profile},
+  solvers::{solve, Solver, SolverConfig},
+};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+const SEED: u64 = 1;
+
+/// writes a generated input to the temp dir once and returns its path
+fn input(profile: Profile, stations: usize, rows: u64) -> PathBuf {
+  let path = std::env::temp_dir().join(format!(
+    "brc-bench-{:?}-{}-{}-{}.txt",
+    profile, stations, rows, SEED
+  ));
+  if !path.exists() {
+    fs::write(&path, generate_profile(profile, stations, rows, SEED)).unwrap();
+  }
+  path
+}
+
+fn solvers(c: &mut Criterion) {
+  let config = SolverConfig::default();
+  for (rows, stations) in [(100_000, 100), (100_000, 10_000), (1_000_000, 100), (1_000_000, 10_000)] {
+    let path = input(Profile::Weather, stations, rows);
+    let mut group = c.benchmark_group(format!("solvers/{}-rows/{}-stations", rows, stations));
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(rows));
+    for solver in Solver::ALL {
+      group.bench_function(BenchmarkId::from_parameter(solver), |b| {
+        b.iter(|| solve(solver, &path, &config))
+      });
+    }
+    group.finish();
+  }
+}
+
+/// the current solver on each of the adversarial profiles
+fn profiles(c: &mut Criterion) {
+  let config = SolverConfig::default();
+  let rows = 100_000;
+  let mut group = c.benchmark_group("profiles/v15");
+  group.sample_size(10);
+  group.throughput(Throughput::Elements(rows));
+  for profile in Profile::ALL {
+    let path = input(profile, 10_000, rows);
+    group.bench_function(BenchmarkId::from_parameter(format!("{:?}", profile)), |b| {
+      b.iter(|| solve(Solver::V15, &path, &config))
+    });
+  }
+  group.finish();
+}
+
+criterion_group!(benches, solvers, profiles);
+criterion_main!(benches);
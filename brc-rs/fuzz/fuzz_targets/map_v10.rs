@@ -0,0 +1,27 @@
+//! Arbitrary names through the v10 map, checked against a `HashMap`.
+#![no_main]
+
+use std::collections::HashMap;
+
+use brc_rs::solve_v10::FixedSizeMap;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|names: Vec<&[u8]>| {
+  let mut map = FixedSizeMap::new();
+  let mut expected = HashMap::new();
+  // the parser never passes an empty name
+  for name in names.into_iter().filter(|name| !name.is_empty()) {
+    match map.get_or_insert(name) {
+      Some(record) => {
+        record.num += 1;
+        *expected.entry(name).or_insert(0) += 1;
+      }
+      None => assert!(!expected.contains_key(name), "{:?} was dropped", name),
+    }
+  }
+
+  assert_eq!(map.keys().len(), expected.len());
+  for (name, num) in expected {
+    assert_eq!(map.get(name).unwrap().num, num);
+  }
+});
@@ -0,0 +1,47 @@
+//! Arbitrary bytes through the v10 parsing loop. It may reject them but must
+//! not panic, and whatever it accepts has to add up to the same records as a
+//! plain line by line parse.
+#![no_main]
+
+use std::collections::HashMap;
+
+use brc_rs::{
+  format::InputFormat,
+  names::{NameConfig, NamePolicy},
+  solve_v10::{parse, FixedSizeMap},
+};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+  let mut map = FixedSizeMap::new();
+  // names are counted as they are, like the line by line parse below
+  let names = NameConfig {
+    policy: NamePolicy::Allow,
+    ..Default::default()
+  };
+  let format = InputFormat::default();
+  if parse(data, &names, &format, &mut map).is_err() {
+    return;
+  }
+
+  let mut expected: HashMap<&[u8], (i32, i32, i64, usize)> = HashMap::new();
+  // after the byte order mark `parse` skips
+  let data = &data[format.data_start(data)..];
+  let lines = data.strip_suffix(b"\n").unwrap_or(data);
+  for line in lines.split(|&b| b == b'\n').filter(|_| !data.is_empty()) {
+    let semicolon = line.iter().position(|&b| b == b';').unwrap();
+    let value: f64 = std::str::from_utf8(&line[semicolon + 1..]).unwrap().parse().unwrap();
+    let value = (value * 10.0).round() as i32;
+    let record = expected.entry(&line[..semicolon]).or_insert((i32::MAX, i32::MIN, 0, 0));
+    record.0 = record.0.min(value);
+    record.1 = record.1.max(value);
+    record.2 += value as i64;
+    record.3 += 1;
+  }
+
+  assert_eq!(map.keys().len(), expected.len());
+  for (name, (min, max, total, num)) in expected {
+    let record = map.get(name).unwrap();
+    assert_eq!((record.min, record.max, record.total, record.num), (min, max, total, num));
+  }
+});
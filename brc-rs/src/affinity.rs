@@ -0,0 +1,193 @@
+//! Thread count and cpu pinning for the parallel solvers, so benchmark runs on
+//! shared machines are reproducible.
+
+use std::{fs, io, thread};
+
+/// cpus a `cpu_set_t` has room for (`CPU_SETSIZE`)
+pub const MAX_CPUS: usize = 1024;
+
+#[derive(Clone, Debug, Default)]
+pub struct ThreadConfig {
+  /// number of worker threads, defaults to one per allowed cpu
+  pub threads: Option<usize>,
+  /// pin workers round robin to these cpus
+  pub cpus: Option<Vec<usize>>,
+  /// only use cpus of this numa node
+  pub numa_node: Option<usize>,
+}
+
+/// what the workers should actually do after resolving a `ThreadConfig`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ThreadPlan {
+  pub threads: usize,
+  /// empty if threads aren't pinned
+  pub cpus: Vec<usize>,
+}
+
+impl ThreadPlan {
+  /// pins the calling thread to the cpu assigned to `worker`, if any
+  pub fn pin_worker(&self, worker: usize) -> io::Result<()> {
+    if self.cpus.is_empty() {
+      return Ok(());
+    }
+    pin_current_thread(self.cpus[worker % self.cpus.len()])
+  }
+}
+
+impl ThreadConfig {
+  pub fn resolve(&self) -> io::Result<ThreadPlan> {
+    let cpus = match (&self.cpus, self.numa_node) {
+      (cpus, Some(node)) => {
+        let node_cpus = numa_node_cpus(node)?;
+        match cpus {
+          Some(cpus) => cpus.iter().copied().filter(|c| node_cpus.contains(c)).collect(),
+          None => node_cpus,
+        }
+      }
+      (Some(cpus), None) => cpus.clone(),
+      (None, None) => Vec::new(),
+    };
+    if cpus.is_empty() && (self.cpus.is_some() || self.numa_node.is_some()) {
+      return Err(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        "no cpus left to run on",
+      ));
+    }
+
+    let threads = match self.threads {
+      Some(threads) => threads.max(1),
+      None if !cpus.is_empty() => cpus.len(),
+      None => thread::available_parallelism()?.get(),
+    };
+    Ok(ThreadPlan { threads, cpus })
+  }
+}
+
+/// parses the kernel's cpu list format, e.g. `0-3,8,10-11`, keeping the
+/// first of any duplicates
+pub fn parse_cpu_list(list: &str) -> Result<Vec<usize>, String> {
+  let mut cpus = Vec::new();
+  for part in list.trim().split(',').filter(|p| !p.is_empty()) {
+    let parse = |s: &str| {
+      let cpu = s
+        .trim()
+        .parse::<usize>()
+        .map_err(|e| format!("invalid cpu {:?}: {}", s, e))?;
+      if cpu >= MAX_CPUS {
+        return Err(format!("cpu {} is out of range, cpus go up to {}", cpu, MAX_CPUS - 1));
+      }
+      Ok(cpu)
+    };
+    let (first, last) = match part.split_once('-') {
+      Some((first, last)) => (parse(first)?, parse(last)?),
+      None => (parse(part)?, parse(part)?),
+    };
+    if first > last {
+      return Err(format!("invalid cpu range {:?}", part));
+    }
+    for cpu in first..=last {
+      if !cpus.contains(&cpu) {
+        cpus.push(cpu);
+      }
+    }
+  }
+  Ok(cpus)
+}
+
+pub fn numa_node_cpus(node: usize) -> io::Result<Vec<usize>> {
+  let list = fs::read_to_string(format!("/sys/devices/system/node/node{}/cpulist", node))?;
+  parse_cpu_list(&list).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+#[cfg(target_os = "linux")]
+pub fn pin_current_thread(cpu: usize) -> io::Result<()> {
+  if cpu >= MAX_CPUS {
+    return Err(io::Error::new(
+      io::ErrorKind::InvalidInput,
+      format!("cpu {} is out of range, cpus go up to {}", cpu, MAX_CPUS - 1),
+    ));
+  }
+  unsafe {
+    let mut set: libc::cpu_set_t = std::mem::zeroed();
+    libc::CPU_SET(cpu, &mut set);
+    if libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) != 0 {
+      return Err(io::Error::last_os_error());
+    }
+  }
+  Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn pin_current_thread(_cpu: usize) -> io::Result<()> {
+  Err(io::Error::new(
+    io::ErrorKind::Unsupported,
+    "pinning threads is only supported on linux",
+  ))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parses_cpu_lists() {
+    assert_eq!(parse_cpu_list("0-3,8,10-11\n"), Ok(vec![0, 1, 2, 3, 8, 10, 11]));
+    assert_eq!(parse_cpu_list(" 5 , 2-3"), Ok(vec![5, 2, 3]));
+    assert_eq!(parse_cpu_list("1,0-2,1"), Ok(vec![1, 0, 2]));
+    assert_eq!(parse_cpu_list("1023"), Ok(vec![1023]));
+    assert_eq!(parse_cpu_list(""), Ok(vec![]));
+    for list in ["a", "1-", "-1", "3-1", "0-1-2", "1.5", "1024", "0-1024", "99999999999999999999"] {
+      assert!(parse_cpu_list(list).is_err(), "{:?}", list);
+    }
+  }
+
+  #[test]
+  fn resolves_thread_counts() {
+    let resolve = |threads, cpus: Option<Vec<usize>>| {
+      ThreadConfig {
+        threads,
+        cpus,
+        numa_node: None,
+      }
+      .resolve()
+      .unwrap()
+    };
+    let available = thread::available_parallelism().unwrap().get();
+    assert_eq!(resolve(None, None), ThreadPlan { threads: available, cpus: vec![] });
+    assert_eq!(resolve(Some(0), None).threads, 1);
+    assert_eq!(resolve(None, Some(vec![0, 2])), ThreadPlan { threads: 2, cpus: vec![0, 2] });
+    // more threads than cpus share them round robin
+    assert_eq!(resolve(Some(5), Some(vec![0, 2])), ThreadPlan { threads: 5, cpus: vec![0, 2] });
+
+    let no_cpus = ThreadConfig {
+      cpus: Some(Vec::new()),
+      ..Default::default()
+    };
+    assert!(no_cpus.resolve().is_err());
+    let missing_node = ThreadConfig {
+      numa_node: Some(MAX_CPUS),
+      ..Default::default()
+    };
+    assert!(missing_node.resolve().is_err());
+  }
+
+  #[cfg(target_os = "linux")]
+  #[test]
+  fn pins_threads() {
+    // on a thread of its own, so the test runner's threads aren't pinned
+    thread::spawn(|| {
+      let cpu = unsafe { libc::sched_getcpu() } as usize;
+      pin_current_thread(cpu).unwrap();
+      assert_eq!(unsafe { libc::sched_getcpu() } as usize, cpu);
+      let plan = ThreadPlan {
+        threads: 3,
+        cpus: vec![cpu],
+      };
+      plan.pin_worker(2).unwrap();
+      assert!(pin_current_thread(MAX_CPUS).is_err());
+      assert!(pin_current_thread(usize::MAX).is_err());
+    })
+    .join()
+    .unwrap();
+  }
+}
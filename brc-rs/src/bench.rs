@@ -0,0 +1,177 @@
+//! Timing solvers against each other on the same input, the way evaluate2.sh
+//! scores entries: warm up, time a few runs, drop the fastest and slowest and
+//! average the rest.
+
+use std::{
+  fs::File,
+  io::{self, Write},
+  os::fd::AsRawFd,
+  path::Path,
+  time::Instant,
+};
+
+use crate::solvers::{solve, Solver, SolverConfig};
+
+pub struct BenchResult {
+  pub solver: Solver,
+  /// seconds per timed run, in the order they ran
+  pub times: Vec<f64>,
+  /// whether the output is the same as the first solver's
+  pub output_matches: bool,
+}
+
+/// `times` without the fastest and slowest run, unless that would leave
+/// nothing
+pub fn trimmed(times: &[f64]) -> Vec<f64> {
+  let mut sorted = times.to_vec();
+  sorted.sort_by(f64::total_cmp);
+  if sorted.len() >= 3 {
+    sorted[1..sorted.len() - 1].to_vec()
+  } else {
+    sorted
+  }
+}
+
+/// mean and sample standard deviation
+pub fn mean_stddev(times: &[f64]) -> (f64, f64) {
+  let mean = times.iter().sum::<f64>() / times.len() as f64;
+  if times.len() < 2 {
+    return (mean, 0.0);
+  }
+  let variance = times.iter().map(|t| (t - mean).powi(2)).sum::<f64>() / (times.len() - 1) as f64;
+  (mean, variance.sqrt())
+}
+
+/// Points stdout at /dev/null until it's dropped, so what the solvers print
+/// doesn't end up in the middle of the table.
+struct SilencedStdout {
+  saved_fd: i32,
+}
+
+impl SilencedStdout {
+  fn new() -> io::Result<Self> {
+    io::stdout().flush()?;
+    let null = File::options().write(true).open("/dev/null")?;
+    let saved_fd = unsafe { libc::dup(libc::STDOUT_FILENO) };
+    if saved_fd < 0 {
+      return Err(io::Error::last_os_error());
+    }
+    if unsafe { libc::dup2(null.as_raw_fd(), libc::STDOUT_FILENO) } < 0 {
+      let error = io::Error::last_os_error();
+      unsafe { libc::close(saved_fd) };
+      return Err(error);
+    }
+    Ok(Self { saved_fd })
+  }
+}
+
+impl Drop for SilencedStdout {
+  fn drop(&mut self) {
+    let _ = io::stdout().flush();
+    unsafe {
+      libc::dup2(self.saved_fd, libc::STDOUT_FILENO);
+      libc::close(self.saved_fd);
+    }
+  }
+}
+
+/// Runs every solver `warmup` times untimed and then `runs` times timed on
+/// `input`, comparing their outputs to the first solver's. `runs` has to be
+/// at least 3 so there's something left after dropping the fastest and
+/// slowest. Progress goes to stderr, the solvers' own output nowhere.
+pub fn bench(
+  solvers: &[Solver],
+  input: &Path,
+  config: &SolverConfig,
+  warmup: usize,
+  runs: usize,
+) -> Vec<BenchResult> {
+  assert!(runs >= 3, "need at least 3 runs, not {}", runs);
+  let _silenced = SilencedStdout::new().unwrap();
+  let mut expected = None;
+  let mut results = Vec::new();
+  for &solver in solvers {
+    for _ in 0..warmup {
+      solve(solver, input, config);
+    }
+    let mut times = Vec::new();
+    let mut output_matches = true;
+    for run in 0..runs {
+      let start = Instant::now();
+      let output = solve(solver, input, config);
+      let time = start.elapsed().as_secs_f64();
+      eprintln!("{} run {}: {:.3}s", solver, run + 1, time);
+      times.push(time);
+      output_matches &= *expected.get_or_insert_with(|| output.clone()) == output;
+    }
+    results.push(BenchResult {
+      solver,
+      times,
+      output_matches,
+    });
+  }
+  results
+}
+
+/// a table for the README's benchmarks section
+pub fn markdown_table(results: &[BenchResult]) -> String {
+  let mut table = String::new();
+  table.push_str("| solver | mean (s) | stddev (s) | runs | output |\n");
+  table.push_str("|--------|---------:|-----------:|-----:|--------|\n");
+  for result in results {
+    let kept = trimmed(&result.times);
+    let (mean, stddev) = mean_stddev(&kept);
+    table.push_str(&format!(
+      "| {} | {:.3} | {:.3} | {} of {} | {} |\n",
+      result.solver,
+      mean,
+      stddev,
+      kept.len(),
+      result.times.len(),
+      if result.output_matches { "ok" } else { "differs" },
+    ));
+  }
+  table
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn trims_fastest_and_slowest() {
+    assert_eq!(trimmed(&[3.0, 1.0, 2.0, 9.0, 2.5]), vec![2.0, 2.5, 3.0]);
+    assert_eq!(trimmed(&[2.0, 1.0]), vec![1.0, 2.0]);
+  }
+
+  #[test]
+  fn computes_mean_and_stddev() {
+    assert_eq!(mean_stddev(&[2.0]), (2.0, 0.0));
+    let (mean, stddev) = mean_stddev(&[2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0]);
+    assert_eq!(mean, 5.0);
+    assert!((stddev - 2.138).abs() < 1e-3);
+  }
+
+  #[test]
+  fn formats_a_table() {
+    let results = [
+      BenchResult {
+        solver: Solver::V5,
+        times: vec![1.0, 1.5, 2.0, 2.5, 10.0],
+        output_matches: true,
+      },
+      BenchResult {
+        solver: Solver::V10,
+        times: vec![0.5],
+        output_matches: false,
+      },
+    ];
+    assert_eq!(
+      markdown_table(&results),
+      "| solver | mean (s) | stddev (s) | runs | output |\n\
+       |--------|---------:|-----------:|-----:|--------|\n\
+       | v5 | 2.000 | 0.500 | 3 of 5 | ok |\n\
+       | v10 | 0.500 | 0.000 | 1 of 1 | differs |\n"
+    );
+  }
+}
@@ -0,0 +1,340 @@
+//! Input that isn't only the challenge's `name;temperature` lines, like csv
+//! exports with a header row or `data/weather_stations.csv` with its `#`
+//! comments. v10 and v15 read it, the older solvers only take plain lines.
+//!
+//! v10 and v15 always skip a utf-8 byte order mark at the start, which some
+//! windows tools write.
+//!
+//! With missing values configured, rows with one of them only count towards
+//! their station's `missing` count, which the output adds after min/mean/max.
+
+use std::ops::Range;
+#[cfg(unix)]
+use std::{
+  fs::File,
+  io::{self, BufRead, BufReader, Read},
+  os::unix::fs::FileExt,
+};
+
+use memchr::memchr;
+
+pub const BYTE_ORDER_MARK: &[u8] = b"\xef\xbb\xbf";
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct InputFormat {
+  /// lines starting with this are skipped wherever they are
+  pub comment_prefix: Option<String>,
+  /// lines to skip at the start of the input, e.g. a csv header
+  pub header_lines: usize,
+  /// trim ascii whitespace around names and values, e.g. `Paris; 12.3\r`
+  pub trim: bool,
+  pub number: NumberFormat,
+  /// values that mark a measurement as missing, e.g. `NA` or an empty one
+  pub missing: Vec<String>,
+  /// temperatures in tenths that mark a measurement as missing, e.g. -9999
+  /// for `-999.9`
+  pub sentinels: Vec<i32>,
+}
+
+/// the character between the whole degrees and the tenths
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum DecimalSeparator {
+  /// `12.3`
+  #[default]
+  Point,
+  /// `12,3`, as in most of europe
+  Comma,
+}
+
+impl DecimalSeparator {
+  pub fn byte(self) -> u8 {
+    match self {
+      DecimalSeparator::Point => b'.',
+      DecimalSeparator::Comma => b',',
+    }
+  }
+}
+
+/// how temperatures are written, the output always uses `12.3`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct NumberFormat {
+  pub decimal: DecimalSeparator,
+  /// between groups of digits before the decimal separator, e.g. `.` in
+  /// `1.234,5`
+  pub thousands: Option<u8>,
+}
+
+impl NumberFormat {
+  pub fn new(decimal: DecimalSeparator, thousands: Option<u8>) -> Result<Self, String> {
+    if thousands == Some(decimal.byte()) {
+      return Err(format!(
+        "the thousands separator can't also be the decimal separator {:?}",
+        decimal.byte() as char
+      ));
+    }
+    Ok(Self { decimal, thousands })
+  }
+
+  /// `12.3` without thousands separators, which the fast paths parse
+  pub fn is_plain(self) -> bool {
+    self == Self::default()
+  }
+
+  /// A temperature in tenths of a degree: an optional `-`, digits with
+  /// thousands separators between them, the decimal separator and one digit.
+  /// `None` for anything else or more than fits an `i32`.
+  pub fn parse_tenths(self, field: &[u8]) -> Option<i32> {
+    let (sign, field) = match field.strip_prefix(b"-") {
+      Some(rest) => (-1, rest),
+      None => (1, field),
+    };
+    let [integer @ .., decimal, tenths] = field else {
+      return None;
+    };
+    if integer.is_empty() || *decimal != self.decimal.byte() || !tenths.is_ascii_digit() {
+      return None;
+    }
+    let mut value: i32 = 0;
+    for (i, b) in integer.iter().enumerate() {
+      if b.is_ascii_digit() {
+        value = value.checked_mul(10)?.checked_add((b - b'0') as i32)?;
+      } else if Some(*b) != self.thousands
+        || i == 0
+        || !integer[i - 1].is_ascii_digit()
+        || !integer.get(i + 1).is_some_and(u8::is_ascii_digit)
+      {
+        return None;
+      }
+    }
+    Some(sign * value.checked_mul(10)?.checked_add((tenths - b'0') as i32)?)
+  }
+}
+
+/// parses a thousands separator, any ascii punctuation or a space that can't
+/// be confused with the rest of a line
+pub fn parse_separator(separator: &str) -> Result<u8, String> {
+  match separator.as_bytes() {
+    [b] if (b.is_ascii_punctuation() && !matches!(b, b'-' | b';')) || *b == b' ' => Ok(*b),
+    _ => Err(format!(
+      "expected one ascii punctuation character other than '-' and ';' or a space, got {:?}",
+      separator
+    )),
+  }
+}
+
+impl InputFormat {
+  /// nothing but `name;temperature` lines, which every solver reads
+  pub fn is_plain(&self) -> bool {
+    *self == Self::default()
+  }
+
+  /// Whether every line after the header is `name;temperature`, so the
+  /// fast paths can take them as they are.
+  pub fn lines_are_plain(&self) -> bool {
+    self.comment_prefix.is_none()
+      && !self.trim
+      && self.number.is_plain()
+      && !self.has_missing_values()
+  }
+
+  /// whether some rows may be missing their value, the output then has a
+  /// `missing` count for every station
+  pub fn has_missing_values(&self) -> bool {
+    !self.missing.is_empty() || !self.sentinels.is_empty()
+  }
+
+  /// whether a value `field` is one of the missing values
+  #[inline]
+  pub fn is_missing(&self, field: &[u8]) -> bool {
+    self.missing.iter().any(|missing| missing.as_bytes() == field)
+  }
+
+  /// whether a temperature in tenths is one of the sentinels
+  #[inline]
+  pub fn is_sentinel(&self, value: i32) -> bool {
+    self.sentinels.contains(&value)
+  }
+
+  /// where the measurements start in `bytes`, after the byte order mark and
+  /// header lines
+  pub fn data_start(&self, bytes: &[u8]) -> usize {
+    let mut len = if bytes.starts_with(BYTE_ORDER_MARK) {
+      BYTE_ORDER_MARK.len()
+    } else {
+      0
+    };
+    for _ in 0..self.header_lines {
+      len = match memchr(b'\n', &bytes[len..]) {
+        Some(newline_ind) => len + newline_ind + 1,
+        None => bytes.len(),
+      };
+    }
+    len
+  }
+
+  /// `data_start` of a file, only reading as far as the header goes
+  #[cfg(unix)]
+  pub fn file_data_start(&self, file: &File) -> io::Result<usize> {
+    let mut reader = BufReader::new(ReadAt { file, offset: 0 });
+    let mut len = 0;
+    if reader.fill_buf()?.starts_with(BYTE_ORDER_MARK) {
+      reader.consume(BYTE_ORDER_MARK.len());
+      len += BYTE_ORDER_MARK.len();
+    }
+    for _ in 0..self.header_lines {
+      len += reader.skip_until(b'\n')?;
+    }
+    Ok(len)
+  }
+
+  /// whether the line starting at `line` is a comment
+  #[inline]
+  pub fn is_comment(&self, line: &[u8]) -> bool {
+    match &self.comment_prefix {
+      Some(prefix) => line.starts_with(prefix.as_bytes()),
+      None => false,
+    }
+  }
+
+  /// the `range` of `bytes` a name or value is in, without the whitespace
+  /// around it when trimming
+  #[inline]
+  pub fn field(&self, bytes: &[u8], range: Range<usize>) -> Range<usize> {
+    if !self.trim {
+      return range;
+    }
+    let field = &bytes[range.clone()];
+    let start = range.start + field.len() - field.trim_ascii_start().len();
+    let end = range.end - (field.len() - field.trim_ascii_end().len());
+    start..end.max(start)
+  }
+}
+
+/// reads `file` from `offset` on without moving its cursor
+#[cfg(unix)]
+struct ReadAt<'a> {
+  file: &'a File,
+  offset: u64,
+}
+
+#[cfg(unix)]
+impl Read for ReadAt<'_> {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    let read = self.file.read_at(buf, self.offset)?;
+    self.offset += read as u64;
+    Ok(read)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn skips_header_lines() {
+    let input = b"station;temperature\nunit;celsius\nHamburg;12.0\n";
+    let format = |header_lines| InputFormat {
+      header_lines,
+      ..Default::default()
+    };
+    assert_eq!(format(0).data_start(input), 0);
+    assert_eq!(format(1).data_start(input), 20);
+    assert_eq!(format(2).data_start(input), 33);
+    assert_eq!(format(4).data_start(input), input.len());
+    assert_eq!(format(1).data_start(b"station;temperature"), 19);
+
+    let with_bom = [BYTE_ORDER_MARK, input].concat();
+    assert_eq!(format(0).data_start(&with_bom), 3);
+    assert_eq!(format(1).data_start(&with_bom), 23);
+    assert_eq!(format(0).data_start(b"\xef\xbbHamburg;12.0"), 0);
+
+    #[cfg(unix)]
+    {
+      let path = std::env::temp_dir().join(format!("brc-format-{}.txt", std::process::id()));
+      for input in [&input[..], &with_bom, b"\xef\xbb", b""] {
+        std::fs::write(&path, input).unwrap();
+        let file = File::open(&path).unwrap();
+        for header_lines in 0..5 {
+          let format = format(header_lines);
+          assert_eq!(format.file_data_start(&file).unwrap(), format.data_start(input));
+        }
+      }
+      std::fs::remove_file(&path).unwrap();
+    }
+  }
+
+  #[test]
+  fn trims_fields() {
+    let line = b" Paris \t; 12.3\r";
+    let trim = InputFormat {
+      trim: true,
+      ..Default::default()
+    };
+    assert_eq!(&line[trim.field(line, 0..8)], b"Paris");
+    assert_eq!(&line[trim.field(line, 9..line.len())], b"12.3");
+    assert_eq!(trim.field(b"  ;", 0..2), 2..2);
+    assert_eq!(InputFormat::default().field(line, 0..8), 0..8);
+  }
+
+  #[test]
+  fn parses_numbers() {
+    let parse = |decimal, thousands, field: &str| {
+      let format = NumberFormat::new(decimal, thousands).unwrap();
+      format.parse_tenths(field.as_bytes())
+    };
+    let (point, comma) = (DecimalSeparator::Point, DecimalSeparator::Comma);
+    assert_eq!(parse(point, None, "12.3"), Some(123));
+    assert_eq!(parse(point, None, "-0.7"), Some(-7));
+    assert_eq!(parse(point, None, "12,3"), None);
+    assert_eq!(parse(comma, None, "12,3"), Some(123));
+    assert_eq!(parse(comma, None, "-99,9"), Some(-999));
+    assert_eq!(parse(comma, None, "12.3"), None);
+    assert_eq!(parse(comma, Some(b'.'), "-1.234,5"), Some(-12345));
+    assert_eq!(parse(point, Some(b','), "1,234,567.8"), Some(12345678));
+    assert_eq!(parse(point, Some(b'\''), "1'234.5"), Some(12345));
+    assert_eq!(parse(point, Some(b' '), "1 234.5"), Some(12345));
+
+    for field in ["", "-", ".5", "-.5", "12.", "12.34", "1..2", "+1.2", "1.2\n", "a.1"] {
+      assert_eq!(parse(point, None, field), None, "{:?}", field);
+    }
+    for field in [",234.5", "1,,234.5", "1,.5", "-,1.0", "1.234,5"] {
+      assert_eq!(parse(point, Some(b','), field), None, "{:?}", field);
+    }
+    assert_eq!(parse(point, None, "214748364.7"), Some(i32::MAX));
+    assert_eq!(parse(point, None, "214748364.8"), None);
+
+    assert!(NumberFormat::new(comma, Some(b',')).is_err());
+    assert_eq!(parse_separator("."), Ok(b'.'));
+    assert_eq!(parse_separator(" "), Ok(b' '));
+    for separator in ["", "-", ";", "1", "a", "..", "\u{a0}"] {
+      assert!(parse_separator(separator).is_err(), "{:?}", separator);
+    }
+  }
+
+  #[test]
+  fn matches_missing_values() {
+    let format = InputFormat {
+      missing: vec!["NA".to_string(), String::new()],
+      sentinels: vec![-9999],
+      ..Default::default()
+    };
+    assert!(format.has_missing_values() && !format.lines_are_plain());
+    assert!(format.is_missing(b"NA") && format.is_missing(b""));
+    assert!(!format.is_missing(b"na") && !format.is_missing(b"NA ") && !format.is_missing(b"0.0"));
+    assert!(format.is_sentinel(-9999) && !format.is_sentinel(9999));
+    assert!(!InputFormat::default().has_missing_values());
+    assert!(!InputFormat::default().is_missing(b""));
+  }
+
+  #[test]
+  fn matches_comment_prefixes() {
+    assert!(!InputFormat::default().is_comment(b"# Adapted from"));
+    let format = InputFormat {
+      comment_prefix: Some("//".to_string()),
+      ..Default::default()
+    };
+    assert!(format.is_comment(b"// Adapted from\n"));
+    assert!(!format.is_comment(b"/ Hamburg;12.0\n"));
+    assert!(!format.is_comment(b"Hamburg;12.0 // comment\n"));
+  }
+}
@@ -0,0 +1,478 @@
+//! Writes a measurements file like `create_measurements3.sh` does, without
+//! needing java: stations come from `data/weather_stations.csv`, each with a
+//! mean of `30 * cos(latitude) - 10` and a gaussian spread of 7 degrees.
+//!
+//! Rows are generated in fixed size blocks on every thread and written out in
+//! order by the calling thread.
+
+use std::{
+  collections::{BTreeMap, HashSet},
+  fs::{self, File},
+  io::{self, BufWriter, Write},
+  ops::Range,
+  path::Path,
+  sync::{
+    atomic::{AtomicUsize, Ordering},
+    mpsc,
+  },
+  thread,
+  time::{SystemTime, UNIX_EPOCH},
+};
+
+use memchr::memchr_iter;
+use rand::{Rng, SeedableRng};
+use rand_distr::{StandardNormal, Zipf};
+use rand_xoshiro::Xoshiro256PlusPlus;
+
+use crate::{affinity::ThreadPlan, profile::Profile};
+
+/// spread of every station's measurements, the same as the reference
+/// generator
+pub const STD_DEV: f64 = 7.0;
+pub const ROWS_PER_BLOCK: u64 = 1 << 20;
+
+pub struct Station {
+  pub name: Vec<u8>,
+  pub mean: f64,
+}
+
+/// how rows pick their station
+pub enum Picks {
+  Uniform,
+  /// the `n`th station is picked with a probability proportional to
+  /// `1 / n^exponent`
+  Zipf(Zipf<f64>),
+}
+
+/// how rows pick their temperature
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Values {
+  /// around the station's mean with a spread of `STD_DEV`
+  Gaussian,
+  /// only -99.9 and 99.9
+  Extremes,
+}
+
+/// everything that decides what the rows look like, apart from the seed
+pub struct Dataset {
+  pub stations: Vec<Station>,
+  pub picks: Picks,
+  pub values: Values,
+}
+
+impl Dataset {
+  /// what the reference generator does
+  pub fn weather(stations: Vec<Station>) -> Self {
+    Self {
+      stations,
+      picks: Picks::Uniform,
+      values: Values::Gaussian,
+    }
+  }
+}
+
+/// Reads the first `max_stations` distinct stations of a `name;latitude`
+/// file, skipping `#` comments.
+pub fn load_stations(path: &Path, max_stations: usize) -> io::Result<Vec<Station>> {
+  let contents = fs::read_to_string(path)?;
+  let mut seen = HashSet::new();
+  let mut stations = Vec::new();
+  for line in contents.lines() {
+    if stations.len() == max_stations {
+      break;
+    }
+    if line.is_empty() || line.starts_with('#') {
+      continue;
+    }
+    let invalid = || {
+      io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("invalid station line {:?}", line),
+      )
+    };
+    let (name, latitude) = line.split_once(';').ok_or_else(invalid)?;
+    let latitude: f64 = latitude.trim().parse().map_err(|_| invalid())?;
+    if !seen.insert(name) {
+      continue;
+    }
+    stations.push(Station {
+      name: name.as_bytes().to_vec(),
+      mean: 30.0 * latitude.to_radians().cos() - 10.0,
+    });
+  }
+  Ok(stations)
+}
+
+/// `value` rounded to tenths, clamped to what the solvers accept
+pub fn to_tenths(value: f64) -> i32 {
+  ((value * 10.0).round() as i32).clamp(-999, 999)
+}
+
+/// appends `tenths` formatted like `-12.3`
+pub fn push_temperature(out: &mut Vec<u8>, tenths: i32) {
+  if tenths < 0 {
+    out.push(b'-');
+  }
+  let abs = tenths.unsigned_abs();
+  if abs >= 100 {
+    out.push(b'0' + (abs / 100) as u8);
+  }
+  out.extend_from_slice(&[b'0' + (abs / 10 % 10) as u8, b'.', b'0' + (abs % 10) as u8]);
+}
+
+/// Draws `rows` rows from an rng seeded by `seed` and calls `row` with the
+/// station and temperature of each.
+fn draw_rows(dataset: &Dataset, seed: u64, rows: u64, mut row: impl FnMut(&Station, i32)) {
+  let stations = &dataset.stations;
+  let mut rng = Xoshiro256PlusPlus::seed_from_u64(seed);
+  for _ in 0..rows {
+    let station = match &dataset.picks {
+      // u32 so the draws don't depend on the pointer width
+      Picks::Uniform => &stations[rng.random_range(0..stations.len() as u32) as usize],
+      Picks::Zipf(zipf) => &stations[rng.sample(zipf) as usize - 1],
+    };
+    let tenths = match dataset.values {
+      Values::Gaussian => {
+        let z: f64 = rng.sample(StandardNormal);
+        to_tenths(station.mean + z * STD_DEV)
+      }
+      Values::Extremes => {
+        if rng.random() {
+          999
+        } else {
+          -999
+        }
+      }
+    };
+    row(station, tenths);
+  }
+}
+
+/// appends `rows` rows to `out`, drawn with an rng seeded by `seed`
+fn generate_block(dataset: &Dataset, seed: u64, rows: u64, out: &mut Vec<u8>) {
+  // growing the buffer as we go costs about as much as generating the rows
+  let longest = dataset.stations.iter().map(|s| s.name.len()).max().unwrap_or(0);
+  out.reserve(rows as usize * (longest + 7));
+  draw_rows(dataset, seed, rows, |station, tenths| {
+    out.extend_from_slice(&station.name);
+    out.push(b';');
+    push_temperature(out, tenths);
+    out.push(b'\n');
+  });
+}
+
+/// length in bytes of what `generate_block` would append
+fn block_len(dataset: &Dataset, seed: u64, rows: u64) -> u64 {
+  let mut len = 0;
+  draw_rows(dataset, seed, rows, |station, tenths| {
+    let digits = if tenths.abs() >= 100 { 3 } else { 2 };
+    len += station.name.len() + (tenths < 0) as usize + digits + 3;
+  });
+  len as u64
+}
+
+/// seed of a block, so blocks don't depend on which thread made them
+fn block_seed(seed: u64, block: u64) -> u64 {
+  Xoshiro256PlusPlus::seed_from_u64(seed ^ block.wrapping_mul(0x9e37_79b9_7f4a_7c15)).random()
+}
+
+pub fn random_seed() -> u64 {
+  SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos() as u64
+}
+
+/// which part of the file to write
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Selection {
+  All,
+  /// rows `start..end`, counting from 0
+  Rows(Range<u64>),
+  /// bytes `start..end`, these can cut lines in half
+  Bytes(Range<u64>),
+}
+
+/// parses `start..end`, either side can be left out
+pub fn parse_range(range: &str) -> Result<Range<u64>, String> {
+  let (start, end) = range
+    .split_once("..")
+    .ok_or_else(|| format!("expected start..end, got {:?}", range))?;
+  let parse = |s: &str, default| match s.trim() {
+    "" => Ok(default),
+    s => s.parse::<u64>().map_err(|e| format!("invalid bound {:?}: {}", s, e)),
+  };
+  Ok(parse(start, 0)?..parse(end, u64::MAX)?)
+}
+
+/// rows in `block` of a file with `rows` rows
+fn rows_in_block(block: usize, rows: u64) -> u64 {
+  let start = block as u64 * ROWS_PER_BLOCK;
+  (start + ROWS_PER_BLOCK).min(rows) - start
+}
+
+/// byte offset just past the `n`th newline of `buffer`, 0 for `n == 0`
+fn row_offset(buffer: &[u8], n: u64) -> usize {
+  if n == 0 {
+    return 0;
+  }
+  memchr_iter(b'\n', buffer)
+    .nth(n as usize - 1)
+    .map_or(buffer.len(), |ind| ind + 1)
+}
+
+/// Byte offsets of the starts of blocks, up to the first block starting at or
+/// past `end` (or the end of the file). Every block before has to be drawn to
+/// know its length, so this takes about as long as generating them.
+fn block_offsets(
+  dataset: &Dataset,
+  rows: u64,
+  seed: u64,
+  end: u64,
+  plan: &ThreadPlan,
+) -> Vec<u64> {
+  let num_blocks = rows.div_ceil(ROWS_PER_BLOCK) as usize;
+  let mut offsets = vec![0];
+  while offsets.len() <= num_blocks && *offsets.last().unwrap() < end {
+    let first = offsets.len() - 1;
+    let batch = first..(first + plan.threads).min(num_blocks);
+    let lens: Vec<u64> = thread::scope(|s| {
+      let sizers: Vec<_> = batch
+        .map(|block| {
+          let block_rows = rows_in_block(block, rows);
+          s.spawn(move || block_len(dataset, block_seed(seed, block as u64), block_rows))
+        })
+        .collect();
+      sizers.into_iter().map(|sizer| sizer.join().unwrap()).collect()
+    });
+    for len in lens {
+      offsets.push(offsets.last().unwrap() + len);
+    }
+  }
+  offsets
+}
+
+/// which bytes of a generated block end up in the output
+type Trim = Box<dyn Fn(usize, &[u8]) -> Range<usize>>;
+
+/// Writes the `selection` of a `rows` row file to `output` using
+/// `plan.threads` generator threads.
+///
+/// The file only depends on `stations`, `rows` and `seed`: every block of
+/// `ROWS_PER_BLOCK` rows has its own rng, so neither the thread count nor the
+/// selection change what a row looks like, and writing the selections of
+/// consecutive ranges gives the same bytes as writing the whole file.
+pub fn generate(
+  dataset: &Dataset,
+  rows: u64,
+  seed: u64,
+  selection: &Selection,
+  plan: &ThreadPlan,
+  output: &mut impl Write,
+) -> io::Result<()> {
+  if dataset.stations.is_empty() {
+    return Err(io::Error::new(
+      io::ErrorKind::InvalidInput,
+      "no stations to generate rows for",
+    ));
+  }
+  let num_blocks = rows.div_ceil(ROWS_PER_BLOCK) as usize;
+
+  // the blocks to generate and which part of each block to keep
+  let (blocks, trim): (Range<usize>, Trim) = match selection {
+    Selection::All => (0..num_blocks, Box::new(|_, buffer: &[u8]| 0..buffer.len())),
+    Selection::Rows(range) => {
+      let (start, end) = (range.start.min(rows), range.end.min(rows));
+      let blocks = if start < end {
+        (start / ROWS_PER_BLOCK) as usize..end.div_ceil(ROWS_PER_BLOCK) as usize
+      } else {
+        0..0
+      };
+      let trim = move |block: usize, buffer: &[u8]| {
+        let first_row = block as u64 * ROWS_PER_BLOCK;
+        let keep_start = start.saturating_sub(first_row);
+        let keep_end = (end - first_row).min(ROWS_PER_BLOCK);
+        row_offset(buffer, keep_start)..row_offset(buffer, keep_end)
+      };
+      (blocks, Box::new(trim))
+    }
+    Selection::Bytes(range) => {
+      let offsets = block_offsets(dataset, rows, seed, range.end, plan);
+      // either the file's length or past the end of the range
+      let sized_len = *offsets.last().unwrap();
+      let (start, end) = (range.start.min(sized_len), range.end.min(sized_len));
+      let blocks = if start < end {
+        let first = offsets.partition_point(|&offset| offset <= start) - 1;
+        let last = offsets.partition_point(|&offset| offset < end);
+        first..last
+      } else {
+        0..0
+      };
+      let trim = move |block: usize, buffer: &[u8]| {
+        let block_start = offsets[block];
+        let keep_start = start.saturating_sub(block_start) as usize;
+        let keep_end = ((end - block_start) as usize).min(buffer.len());
+        keep_start..keep_end
+      };
+      (blocks, Box::new(trim))
+    }
+  };
+
+  let next_block = AtomicUsize::new(blocks.start);
+  // a couple of blocks per thread in flight keeps the writer busy without
+  // buffering the whole file
+  let (block_tx, block_rx) = mpsc::sync_channel::<(usize, Vec<u8>)>(2 * plan.threads);
+  let (free_tx, free_rx) = mpsc::channel::<Vec<u8>>();
+  let free_rx = std::sync::Mutex::new(free_rx);
+
+  thread::scope(|s| -> io::Result<()> {
+    for worker in 0..plan.threads {
+      let (next_block, block_tx, free_rx) = (&next_block, block_tx.clone(), &free_rx);
+      let end = blocks.end;
+      s.spawn(move || -> io::Result<()> {
+        plan.pin_worker(worker)?;
+        loop {
+          let block = next_block.fetch_add(1, Ordering::Relaxed);
+          if block >= end {
+            return Ok(());
+          }
+          let mut buffer = free_rx.lock().unwrap().try_recv().unwrap_or_default();
+          buffer.clear();
+          let block_rows = rows_in_block(block, rows);
+          generate_block(dataset, block_seed(seed, block as u64), block_rows, &mut buffer);
+          if block_tx.send((block, buffer)).is_err() {
+            // the writer failed
+            return Ok(());
+          }
+        }
+      });
+    }
+    drop(block_tx);
+
+    // blocks arrive in any order, write them in order
+    let mut pending = BTreeMap::new();
+    let mut next_write = blocks.start;
+    for (block, buffer) in block_rx {
+      pending.insert(block, buffer);
+      while let Some(buffer) = pending.remove(&next_write) {
+        output.write_all(&buffer[trim(next_write, &buffer)])?;
+        let _ = free_tx.send(buffer);
+        next_write += 1;
+      }
+    }
+    if next_write != blocks.end {
+      return Err(io::Error::other("generator threads exited early"));
+    }
+    Ok(())
+  })?;
+  output.flush()
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn generate_file(
+  profile: Profile,
+  stations_file: &Path,
+  max_stations: usize,
+  rows: u64,
+  seed: u64,
+  selection: &Selection,
+  plan: &ThreadPlan,
+  output: &Path,
+) -> io::Result<()> {
+  let dataset = profile.dataset(stations_file, max_stations, seed)?;
+  let mut output = BufWriter::with_capacity(1 << 20, File::create(output)?);
+  generate(&dataset, rows, seed, selection, plan, &mut output)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn formats_temperatures() {
+    let format = |tenths| {
+      let mut out = Vec::new();
+      push_temperature(&mut out, tenths);
+      String::from_utf8(out).unwrap()
+    };
+    assert_eq!(format(0), "0.0");
+    assert_eq!(format(-5), "-0.5");
+    assert_eq!(format(123), "12.3");
+    assert_eq!(format(-999), "-99.9");
+  }
+
+  #[test]
+  fn generates_requested_rows() {
+    let stations = vec![
+      Station { name: b"Hamburg".to_vec(), mean: 12.0 },
+      Station { name: "Zürich".as_bytes().to_vec(), mean: 9.3 },
+    ];
+    let plan = ThreadPlan { threads: 3, cpus: Vec::new() };
+    let rows = 2 * ROWS_PER_BLOCK + 5;
+    let mut out = Vec::new();
+    generate(&Dataset::weather(stations), rows, 1, &Selection::All, &plan, &mut out).unwrap();
+    let text = String::from_utf8(out).unwrap();
+    assert_eq!(text.lines().count() as u64, rows);
+    for line in text.lines() {
+      let (name, value) = line.split_once(';').unwrap();
+      assert!(name == "Hamburg" || name == "Zürich");
+      let value: f64 = value.parse().unwrap();
+      assert!((-99.9..=99.9).contains(&value));
+    }
+  }
+
+  fn generate_to_vec(rows: u64, seed: u64, selection: Selection, threads: usize) -> Vec<u8> {
+    let stations = vec![
+      Station { name: b"Hamburg".to_vec(), mean: 12.0 },
+      Station { name: b"Istanbul".to_vec(), mean: 13.9 },
+      Station { name: b"Nuuk".to_vec(), mean: -1.7 },
+    ];
+    let plan = ThreadPlan { threads, cpus: Vec::new() };
+    let mut out = Vec::new();
+    let dataset = Dataset::weather(stations);
+    generate(&dataset, rows, seed, &selection, &plan, &mut out).unwrap();
+    out
+  }
+
+  #[test]
+  fn same_seed_gives_same_file() {
+    let rows = ROWS_PER_BLOCK + 1000;
+    let file = generate_to_vec(rows, 42, Selection::All, 1);
+    assert_eq!(generate_to_vec(rows, 42, Selection::All, 4), file);
+    assert_ne!(generate_to_vec(rows, 43, Selection::All, 1), file);
+  }
+
+  #[test]
+  fn ranges_are_slices_of_the_file() {
+    let rows = ROWS_PER_BLOCK + 1000;
+    let file = generate_to_vec(rows, 42, Selection::All, 2);
+    let row_starts: Vec<usize> = std::iter::once(0)
+      .chain(memchr_iter(b'\n', &file).map(|ind| ind + 1))
+      .collect();
+    for range in [0..0, 0..1, 5..ROWS_PER_BLOCK + 3, ROWS_PER_BLOCK..rows, 900..u64::MAX] {
+      let (start, end) = (range.start as usize, range.end.min(rows) as usize);
+      let expected = &file[row_starts[start]..row_starts[end]];
+      assert_eq!(generate_to_vec(rows, 42, Selection::Rows(range), 2), expected);
+    }
+    let len = file.len() as u64;
+    for range in [0..0, 3..7, 10..len - 10, len - 1..len, 100..u64::MAX] {
+      let expected = &file[range.start as usize..range.end.min(len) as usize];
+      assert_eq!(generate_to_vec(rows, 42, Selection::Bytes(range), 2), expected);
+    }
+  }
+
+  #[test]
+  fn parses_ranges() {
+    assert_eq!(parse_range("10..20"), Ok(10..20));
+    assert_eq!(parse_range("..20"), Ok(0..20));
+    assert_eq!(parse_range("10.."), Ok(10..u64::MAX));
+    assert!(parse_range("10-20").is_err());
+  }
+
+  #[test]
+  fn loads_the_station_list() {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/../data/weather_stations.csv");
+    let stations = load_stations(Path::new(path), 10000).unwrap();
+    assert_eq!(stations.len(), 10000);
+    let names: HashSet<_> = stations.iter().map(|s| &s.name).collect();
+    assert_eq!(names.len(), stations.len());
+    assert!(stations.iter().all(|s| (-10.0..=20.0).contains(&s.mean)));
+  }
+}
@@ -0,0 +1,27 @@
+//! What the slice based solvers parse: a mapped file when solving, or bytes
+//! already in memory in tests. Miri can't mmap, so the in-memory form is what
+//! lets it check the unsafe map and parsing code.
+
+use memmap2::Mmap;
+
+pub trait Input: Sync {
+  fn bytes(&self) -> &[u8];
+}
+
+impl Input for Mmap {
+  fn bytes(&self) -> &[u8] {
+    self
+  }
+}
+
+impl Input for [u8] {
+  fn bytes(&self) -> &[u8] {
+    self
+  }
+}
+
+impl Input for Vec<u8> {
+  fn bytes(&self) -> &[u8] {
+    self
+  }
+}
@@ -0,0 +1,51 @@
+pub mod affinity;
+pub mod bench;
+pub mod format;
+pub mod generate;
+pub mod input;
+pub mod mmap;
+pub mod names;
+pub mod perfect_hash;
+pub mod profile;
+pub mod reader;
+pub mod scan;
+pub mod scheduler;
+// older solutions are kept around for comparison
+#[allow(dead_code)]
+pub mod solve_v1;
+#[allow(dead_code)]
+pub mod solve_v2;
+#[allow(dead_code)]
+pub mod solve_v3;
+#[allow(dead_code)]
+pub mod solve_v4;
+#[allow(dead_code)]
+pub mod solve_v5;
+#[allow(dead_code)]
+pub mod solve_v6;
+#[allow(dead_code)]
+pub mod solve_v7;
+#[allow(dead_code)]
+pub mod solve_v8;
+#[allow(dead_code)]
+pub mod solve_v9;
+#[allow(dead_code)]
+pub mod solve_v10;
+#[allow(dead_code)]
+pub mod solve_v11;
+#[allow(dead_code)]
+pub mod solve_v12;
+pub mod solve_v13;
+#[allow(dead_code)]
+pub mod solve_v14;
+pub mod solve_v15;
+pub mod solvers;
+pub mod teardown;
+#[cfg(target_os = "linux")]
+pub mod uring_reader;
+
+pub const MEASUREMENTS: &str = "../measurements.txt";
+pub const WEATHER_STATIONS: &str = "../data/weather_stations.csv";
+pub const OUTPUT_FILE: &str = "/tmp/output.txt";
+pub const MAX_STATION_NAMES: usize = 10000;
+pub const MAX_STATION_NAME_LEN: usize = 100;
@@ -1,36 +1,382 @@
-mod solve_v1;
-mod solve_v2;
-mod solve_v3;
-mod solve_v4;
-mod solve_v5;
-mod solve_v6;
-mod solve_v7;
-mod solve_v8;
-mod solve_v9;
-mod solve_v10;
-
-use solve_v10::solve_v10;
-use solve_v9::solve_v9;
-use solve_v8::solve_v8;
-use solve_v7::solve_v7;
-use solve_v6::solve_v6;
-use solve_v5::solve_v5;
-use solve_v4::solve_v4;
-use solve_v3::solve_v3;
-use solve_v2::solve_v2;
-use solve_v1::solve_v1;
-use std::{fs::File, io::Write};
-
-pub const MEASUREMENTS: &'static str = "../measurements.txt";
-pub const OUTPUT_FILE: &'static str = "/tmp/output.txt";
-pub const MAX_STATION_NAMES: usize = 10000;
-pub const MAX_STATION_NAME_LEN: usize = 100;
-
-pub fn write_string_to_output(s: &str) {
-  let mut output = File::create("/tmp/output.txt").unwrap();
+use brc_rs::{
+  affinity::{parse_cpu_list, ThreadConfig},
+  format::{parse_separator, DecimalSeparator, InputFormat, NumberFormat},
+  bench,
+  generate::{self, parse_range, Selection},
+  mmap::{MmapAdvice, MmapConfig},
+  names::{parse_name_order, NameConfig, NameOrder, NamePolicy, NormalForm, Normalize},
+  profile::Profile,
+  reader::{parse_size, ReaderConfig, ReaderKind},
+  solvers::{solve, Solver, SolverConfig},
+  teardown, MAX_STATION_NAMES, MEASUREMENTS, OUTPUT_FILE, WEATHER_STATIONS,
+};
+use clap::{
+  builder::RangedU64ValueParser, error::ErrorKind, Args, CommandFactory, Parser, Subcommand,
+};
+use std::{
+  fs::File,
+  io::Write,
+  ops::Range,
+  path::{Path, PathBuf},
+};
+
+fn write_string_to_output(s: &str) {
+  let mut output = File::create(OUTPUT_FILE).unwrap();
   output.write_all(s.as_bytes()).unwrap();
+  drop(output);
+  teardown::results_written();
+}
+
+#[derive(Parser)]
+#[command(args_conflicts_with_subcommands = true)]
+struct Cli {
+  #[command(subcommand)]
+  command: Option<Command>,
+
+  #[command(flatten)]
+  solve: SolveArgs,
+}
+
+#[derive(Subcommand)]
+enum Command {
+  /// write a measurements file, like create_measurements3.sh
+  Generate(GenerateArgs),
+  /// time solvers on the same input and print a markdown table
+  Bench(BenchArgs),
+}
+
+#[derive(Args)]
+struct BenchArgs {
+  /// solvers to compare, e.g. v5,v8,v10
+  #[arg(long, value_enum, value_delimiter = ',', required = true)]
+  solvers: Vec<Solver>,
+
+  /// timed runs per solver, at least 3 as the fastest and slowest are
+  /// dropped
+  #[arg(
+    long,
+    default_value_t = 5,
+    value_parser = RangedU64ValueParser::<usize>::new().range(3..)
+  )]
+  runs: usize,
+
+  /// untimed runs per solver before the timed ones
+  #[arg(long, default_value_t = 1)]
+  warmup: usize,
+
+  #[arg(long, default_value = MEASUREMENTS)]
+  input: PathBuf,
+
+  /// number of worker threads for the parallel solver
+  #[arg(long)]
+  threads: Option<usize>,
+}
+
+#[derive(Args)]
+struct GenerateArgs {
+  /// number of rows to write
+  rows: u64,
+
+  /// what the stations and values look like
+  #[arg(long, value_enum, default_value_t)]
+  profile: Profile,
+
+  /// `name;latitude` file to take station names from, for the profiles that
+  /// use weather stations
+  #[arg(long, default_value = WEATHER_STATIONS)]
+  stations_file: PathBuf,
+
+  /// number of distinct stations
+  #[arg(long, default_value_t = MAX_STATION_NAMES)]
+  stations: usize,
+
+  #[arg(long, short, default_value = MEASUREMENTS)]
+  output: PathBuf,
+
+  /// number of generator threads, defaults to one per cpu
+  #[arg(long)]
+  threads: Option<usize>,
+
+  /// the same seed and row count always give the same file, random if not
+  /// given
+  #[arg(long)]
+  seed: Option<u64>,
+
+  /// only write rows `start..end` of the file, e.g. 1000..2000
+  #[arg(long, value_parser = parse_range, conflicts_with = "byte_range")]
+  row_range: Option<Range<u64>>,
+
+  /// only write bytes `start..end` of the file
+  #[arg(long, value_parser = parse_range)]
+  byte_range: Option<Range<u64>>,
+}
+
+#[derive(Args)]
+struct SolveArgs {
+  /// which version to run, defaults to v13 with --catalog and v15 otherwise
+  #[arg(long, value_enum)]
+  solver: Option<Solver>,
+
+  /// station catalog (e.g. ../data/weather_stations.csv) for v13 to build a
+  /// perfect hash over, names not in it are still counted but take the slower
+  /// path
+  #[arg(long)]
+  catalog: Option<PathBuf>,
+
+  /// number of worker threads for the parallel solver, defaults to one per
+  /// allowed cpu
+  #[arg(long)]
+  threads: Option<usize>,
+
+  /// pin worker threads to these cpus, e.g. 0-3,8
+  #[arg(long, value_parser = parse_cpu_list)]
+  cpus: Option<::std::vec::Vec<usize>>,
+
+  /// keep worker threads on the cpus of this numa node
+  #[arg(long)]
+  numa_node: Option<usize>,
+
+  /// how the parallel solver reads the file, `read` keeps memory bounded on
+  /// unix and `io-uring` only works on linux
+  #[arg(long, value_enum, default_value_t)]
+  reader: ReaderKind,
+
+  /// peak memory for buffers and hash maps with `--reader read`, e.g. 256M
+  #[arg(long, value_parser = parse_size, default_value = "256M")]
+  memory_budget: usize,
+
+  /// fault in the whole mapping up front (MAP_POPULATE)
+  #[arg(long)]
+  populate: bool,
+
+  /// madvise hints for the mapping, can be repeated
+  #[arg(long, value_enum)]
+  madvise: Vec<MmapAdvice>,
+
+  /// ask for transparent huge pages on the mapping
+  #[arg(long)]
+  huge_pages: bool,
+
+  /// touch pages from a background thread ahead of the parsers
+  #[arg(long)]
+  prefault: bool,
+
+  /// v15 only: do the work in a forked child and exit as soon as the results
+  /// are written, leaving the child to unmap the file
+  #[arg(long)]
+  fork_teardown: bool,
+
+  /// unicode normal form to bring station names to before counting them, so
+  /// differently encoded spellings of a station are merged
+  #[arg(long, value_enum, default_value_t)]
+  normalize: NormalForm,
+
+  /// merge stations that only differ in case, printing them case folded
+  #[arg(long)]
+  case_fold: bool,
+
+  /// what to do with station names longer than 100 bytes
+  #[arg(long, value_enum, default_value_t)]
+  long_names: NamePolicy,
+
+  /// how to sort stations: `java` like the reference, `bytes`, or
+  /// `locale:<tag>` (e.g. `locale:de`) for reports
+  #[arg(long, value_parser = parse_name_order, default_value = "java")]
+  order: NameOrder,
+
+  /// skip lines starting with this, e.g. `#` for ../data/weather_stations.csv
+  #[arg(long, value_parser = clap::builder::NonEmptyStringValueParser::new())]
+  comment_prefix: Option<String>,
+
+  /// skip this many lines at the start of the input, e.g. a csv header
+  #[arg(long, default_value_t = 0)]
+  header_lines: usize,
+
+  /// trim spaces, tabs and carriage returns around names and values, e.g.
+  /// `Paris; 12.3`
+  #[arg(long)]
+  trim: bool,
+
+  /// decimal separator of the temperatures, e.g. `comma` for `12,3`
+  #[arg(long, value_enum, default_value_t)]
+  decimal: DecimalSeparator,
+
+  /// separator between groups of digits in the temperatures, e.g. `.` for
+  /// `1.234,5`
+  #[arg(long, value_parser = parse_separator)]
+  thousands_separator: Option<u8>,
+
+  /// a value that marks a measurement as missing, e.g. `NA` or `''` for empty
+  /// ones, can be repeated. Missing measurements are counted per station
+  /// instead of going into min/mean/max
+  #[arg(long)]
+  missing: Vec<String>,
+
+  /// a temperature that marks a measurement as missing, written like the
+  /// others, e.g. `-999.9`, can be repeated
+  #[arg(long, allow_hyphen_values = true)]
+  sentinel: Vec<String>,
+}
+
+/// exits with a clap usage error, for arguments that only conflict once
+/// they're looked at together
+fn usage_error(kind: ErrorKind, message: impl std::fmt::Display) -> ! {
+  Cli::command().error(kind, message).exit()
 }
 
 fn main() {
-  solve_v10();
+  let cli = Cli::parse();
+  match cli.command {
+    Some(Command::Generate(args)) => generate(args),
+    Some(Command::Bench(args)) => run_bench(args),
+    None => run_solver(cli.solve),
+  }
+}
+
+fn generate(args: GenerateArgs) {
+  let plan = ThreadConfig {
+    threads: args.threads,
+    ..Default::default()
+  }
+  .resolve()
+  .unwrap();
+  let seed = args.seed.unwrap_or_else(|| {
+    let seed = generate::random_seed();
+    eprintln!("seed: {}", seed);
+    seed
+  });
+  let selection = match (args.row_range, args.byte_range) {
+    (Some(rows), _) => Selection::Rows(rows),
+    (None, Some(bytes)) => Selection::Bytes(bytes),
+    (None, None) => Selection::All,
+  };
+  generate::generate_file(
+    args.profile,
+    Path::new(&args.stations_file),
+    args.stations,
+    args.rows,
+    seed,
+    &selection,
+    &plan,
+    &args.output,
+  )
+  .unwrap();
+}
+
+fn run_bench(args: BenchArgs) {
+  let config = SolverConfig {
+    plan: ThreadConfig {
+      threads: args.threads,
+      ..Default::default()
+    }
+    .resolve()
+    .unwrap(),
+    ..Default::default()
+  };
+  let results = bench::bench(&args.solvers, &args.input, &config, args.warmup, args.runs);
+  println!();
+  print!("{}", bench::markdown_table(&results));
+  let differing: Vec<_> = results
+    .iter()
+    .filter(|r| !r.output_matches)
+    .map(|r| r.solver.to_string())
+    .collect();
+  if !differing.is_empty() {
+    eprintln!(
+      "output of {} differs from {}",
+      differing.join(", "),
+      args.solvers[0]
+    );
+    std::process::exit(1);
+  }
+}
+
+fn run_solver(args: SolveArgs) {
+  let solver = match (args.solver, &args.catalog) {
+    (Some(solver), _) => solver,
+    (None, Some(_)) => Solver::V13,
+    (None, None) => Solver::V15,
+  };
+  // the other solvers unmap before the results are written
+  if args.fork_teardown && solver != Solver::V15 {
+    usage_error(
+      ErrorKind::ArgumentConflict,
+      format!("--fork-teardown only works with v15, not {}", solver),
+    );
+  }
+  let threads = ThreadConfig {
+    threads: args.threads,
+    cpus: args.cpus,
+    numa_node: args.numa_node,
+  };
+  let reader = ReaderConfig {
+    kind: args.reader,
+    memory_budget: args.memory_budget,
+  };
+  let mmap_config = MmapConfig {
+    populate: args.populate,
+    advice: args.madvise,
+    huge_pages: args.huge_pages,
+    prefault: args.prefault,
+  };
+  let number = NumberFormat::new(args.decimal, args.thousands_separator)
+    .unwrap_or_else(|message| usage_error(ErrorKind::ArgumentConflict, message));
+  // parsed here rather than by a value parser as it depends on --decimal and
+  // --thousands-separator
+  let sentinels = args
+    .sentinel
+    .iter()
+    .map(|sentinel| {
+      number.parse_tenths(sentinel.as_bytes()).unwrap_or_else(|| {
+        usage_error(
+          ErrorKind::ValueValidation,
+          format!(
+            "invalid value '{}' for '--sentinel <SENTINEL>': expected a temperature \
+             written with the --decimal and --thousands-separator separators",
+            sentinel
+          ),
+        )
+      })
+    })
+    .collect();
+  let format = InputFormat {
+    comment_prefix: args.comment_prefix,
+    header_lines: args.header_lines,
+    trim: args.trim,
+    number,
+    missing: args.missing,
+    sentinels,
+  };
+  if !format.is_plain() && !matches!(solver, Solver::V10 | Solver::V15) {
+    usage_error(
+      ErrorKind::ArgumentConflict,
+      format!(
+        "{} only reads plain name;temperature lines, use v10 or v15 for other formats",
+        solver
+      ),
+    );
+  }
+  if args.fork_teardown {
+    teardown::fork_for_teardown().unwrap();
+  }
+  let mut config = SolverConfig {
+    plan: threads.resolve().unwrap(),
+    reader,
+    mmap: mmap_config,
+    names: NameConfig {
+      normalize: Normalize {
+        form: args.normalize,
+        case_fold: args.case_fold,
+      },
+      policy: args.long_names,
+      order: args.order,
+    },
+    format,
+    ..Default::default()
+  };
+  if let Some(catalog) = args.catalog {
+    config.catalog = catalog;
+  }
+  let result = solve(solver, Path::new(MEASUREMENTS), &config);
+  write_string_to_output(&result);
 }
@@ -0,0 +1,83 @@
+//! Tuning for the mmap reader: page faults take a visible share of the time
+//! when the file is mapped with the defaults.
+
+use std::{
+  fs::File,
+  hint::black_box,
+  io,
+  sync::atomic::{AtomicBool, Ordering},
+  thread,
+  time::Duration,
+};
+
+use memmap2::{Advice, Mmap, MmapOptions};
+
+use crate::scheduler::ChunkScheduler;
+
+const PAGE_SIZE: usize = 4096;
+/// how far ahead of the parsers the prefault thread stays
+const PREFAULT_AHEAD: usize = 64 * 1024 * 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum MmapAdvice {
+  /// MADV_SEQUENTIAL: aggressive read ahead, pages can be dropped after use
+  Sequential,
+  /// MADV_WILLNEED: start reading the whole file in now
+  WillNeed,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct MmapConfig {
+  /// MAP_POPULATE: fault in the whole file before returning from mmap
+  pub populate: bool,
+  pub advice: Vec<MmapAdvice>,
+  /// ask for transparent huge pages (MADV_HUGEPAGE), only works on
+  /// filesystems that support them for file mappings
+  pub huge_pages: bool,
+  /// touch pages from a background thread ahead of the parsers
+  pub prefault: bool,
+}
+
+pub fn map_file(file: &File, config: &MmapConfig) -> io::Result<Mmap> {
+  let mut options = MmapOptions::new();
+  if config.populate {
+    options.populate();
+  }
+  let mmap = unsafe { options.map(file)? };
+  for advice in &config.advice {
+    mmap.advise(match advice {
+      MmapAdvice::Sequential => Advice::Sequential,
+      MmapAdvice::WillNeed => Advice::WillNeed,
+    })?;
+  }
+  if config.huge_pages {
+    #[cfg(target_os = "linux")]
+    if let Err(e) = mmap.advise(Advice::HugePage) {
+      // not fatal, most filesystems just don't do huge pages
+      eprintln!("could not enable huge pages: {}", e);
+    }
+    #[cfg(not(target_os = "linux"))]
+    eprintln!("huge pages are only supported on linux");
+  }
+  Ok(mmap)
+}
+
+/// Touches one byte per page of `mmap` up to `PREFAULT_AHEAD` bytes past the
+/// scheduler's cursor, so the page faults happen on this thread instead of
+/// the parsers. Returns once everything has been touched or `done` is set.
+pub fn prefault(mmap: &[u8], scheduler: &ChunkScheduler, done: &AtomicBool) {
+  let mut touched = 0;
+  while touched < mmap.len() && !done.load(Ordering::Relaxed) {
+    let target = (scheduler.position() + PREFAULT_AHEAD).min(mmap.len());
+    if touched >= target {
+      thread::sleep(Duration::from_micros(100));
+      continue;
+    }
+    // no point faulting in what the parsers already went past
+    touched = touched.max(scheduler.position() / PAGE_SIZE * PAGE_SIZE);
+    while touched < target {
+      black_box(mmap[touched]);
+      touched += PAGE_SIZE;
+    }
+  }
+}
@@ -0,0 +1,322 @@
+//! Station names are 1 to `MAX_STATION_NAME_LEN` bytes of utf-8. Every solver
+//! runs the names it reads through `Normalize::apply` and `checked_name_len`
+//! before counting them, sorts them with `NameOrder::sort` and prints them with
+//! `name_str`, so they all treat names the same. v10 and v15 already call
+//! `name_str` on a station's first row, so they can say where the name is.
+
+use std::{cmp::Ordering, error::Error, fmt, str};
+
+use icu_casemap::CaseMapper;
+use icu_collator::{Collator, CollatorOptions};
+use icu_locid::Locale;
+use icu_normalizer::ComposingNormalizer;
+
+use crate::MAX_STATION_NAME_LEN;
+
+/// how every solver checks and sorts station names
+#[derive(Clone, Debug, Default)]
+pub struct NameConfig {
+  pub normalize: Normalize,
+  pub policy: NamePolicy,
+  pub order: NameOrder,
+}
+
+/// unicode normal form to convert names to before counting them
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum NormalForm {
+  /// count names as they are
+  #[default]
+  None,
+  /// canonical composition, so a composed and a decomposed `Zürich` are one
+  /// station
+  Nfc,
+  /// compatibility composition, which also merges e.g. fullwidth letters with
+  /// their usual forms
+  Nfkc,
+}
+
+thread_local! {
+  // built once per thread rather than for every non ascii row, they aren't
+  // Sync
+  static CASE_MAPPER: CaseMapper = const { CaseMapper::new() };
+  static NFC: ComposingNormalizer = const { ComposingNormalizer::new_nfc() };
+  static NFKC: ComposingNormalizer = const { ComposingNormalizer::new_nfkc() };
+}
+
+/// rewrites names so that variants of one station are counted together
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Normalize {
+  pub form: NormalForm,
+  /// unicode case folding, the names are printed folded too
+  pub case_fold: bool,
+}
+
+impl Normalize {
+  /// whether names can change at all
+  #[inline]
+  pub fn is_enabled(self) -> bool {
+    self.form != NormalForm::None || self.case_fold
+  }
+
+  /// `name` rewritten into `buffer`, or `None` to count it as it is. Called
+  /// for every row, pure ascii names are already normalized and at most need
+  /// lowercasing.
+  #[inline]
+  pub fn apply<'a>(self, name: &[u8], buffer: &'a mut String) -> Option<&'a str> {
+    if !self.is_enabled() {
+      return None;
+    }
+    if name.is_ascii() {
+      if !self.case_fold || !name.iter().any(u8::is_ascii_uppercase) {
+        return None;
+      }
+      buffer.clear();
+      buffer.extend(name.iter().map(|b| b.to_ascii_lowercase() as char));
+      return Some(buffer);
+    }
+    // invalid utf-8 is counted as it is and fails `name_str`
+    self.apply_unicode(str::from_utf8(name).ok()?, buffer)
+  }
+
+  fn apply_unicode<'a>(self, name: &str, buffer: &'a mut String) -> Option<&'a str> {
+    let folded;
+    let name = if self.case_fold {
+      folded = CASE_MAPPER.with(|case_mapper| case_mapper.fold_string(name));
+      &folded
+    } else {
+      name
+    };
+    buffer.clear();
+    match self.form {
+      NormalForm::None => buffer.push_str(name),
+      NormalForm::Nfc => NFC.with(|nfc| nfc.normalize_to(name, buffer)).unwrap(),
+      NormalForm::Nfkc => NFKC.with(|nfkc| nfkc.normalize_to(name, buffer)).unwrap(),
+    }
+    Some(buffer)
+  }
+}
+
+/// what to do with names longer than `MAX_STATION_NAME_LEN`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum NamePolicy {
+  /// fail on the first long name
+  #[default]
+  Reject,
+  /// count long names under their first `MAX_STATION_NAME_LEN` bytes, cut at
+  /// a char boundary
+  Truncate,
+  /// count long names as they are. The fixed size maps still need room for
+  /// them
+  Allow,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NameError {
+  Empty,
+  /// a name of this many bytes
+  TooLong(usize),
+  InvalidUtf8,
+}
+
+impl fmt::Display for NameError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      NameError::Empty => write!(f, "empty station name"),
+      NameError::TooLong(len) => write!(
+        f,
+        "station name of {} bytes is longer than {}, see --long-names",
+        len, MAX_STATION_NAME_LEN
+      ),
+      NameError::InvalidUtf8 => write!(f, "station name is not valid utf-8"),
+    }
+  }
+}
+
+impl Error for NameError {}
+
+/// How many bytes of `name` to count it under. Called for every row, so
+/// names of a valid length only cost one comparison.
+#[inline]
+pub fn checked_name_len(name: &[u8], policy: NamePolicy) -> Result<usize, NameError> {
+  if (1..=MAX_STATION_NAME_LEN).contains(&name.len()) {
+    return Ok(name.len());
+  }
+  long_name_len(name, policy)
+}
+
+#[cold]
+fn long_name_len(name: &[u8], policy: NamePolicy) -> Result<usize, NameError> {
+  match policy {
+    _ if name.is_empty() => Err(NameError::Empty),
+    NamePolicy::Reject => Err(NameError::TooLong(name.len())),
+    // the last byte that isn't a utf-8 continuation byte (0b10xx_xxxx) starts
+    // the first char that doesn't fit
+    NamePolicy::Truncate => (1..=MAX_STATION_NAME_LEN)
+      .rev()
+      .find(|&i| name[i] & 0xc0 != 0x80)
+      .ok_or(NameError::InvalidUtf8),
+    NamePolicy::Allow => Ok(name.len()),
+  }
+}
+
+/// `name` for printing. Only called once per station.
+pub fn name_str(name: &[u8]) -> Result<&str, NameError> {
+  str::from_utf8(name).map_err(|_| NameError::InvalidUtf8)
+}
+
+/// the order stations are printed in
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum NameOrder {
+  /// utf-16 code units, like the `TreeMap<String>` of the java reference
+  #[default]
+  Java,
+  /// utf-8 bytes, which is code point order
+  Bytes,
+  /// the collation rules of a locale like `de` or `sv`, for reports read by
+  /// people rather than compared to the reference
+  Locale(String),
+}
+
+impl NameOrder {
+  pub fn sort<T: AsRef<[u8]>>(&self, names: &mut [T]) {
+    self.sort_by_name(names, |name| name.as_ref());
+  }
+
+  /// sorts `items` by the station name `name` picks out of each
+  pub fn sort_by_name<T>(&self, items: &mut [T], name: impl Fn(&T) -> &[u8]) {
+    match self {
+      NameOrder::Java => items.sort_by(|a, b| java_cmp(name(a), name(b))),
+      NameOrder::Bytes => items.sort_by(|a, b| name(a).cmp(name(b))),
+      NameOrder::Locale(locale) => {
+        let collator = collator(locale).unwrap();
+        items.sort_by(|a, b| collator.compare_utf8(name(a), name(b)));
+      }
+    }
+  }
+}
+
+/// parses `java`, `bytes` or `locale:<tag>`, e.g. `locale:de`
+pub fn parse_name_order(order: &str) -> Result<NameOrder, String> {
+  match order {
+    "java" => Ok(NameOrder::Java),
+    "bytes" => Ok(NameOrder::Bytes),
+    _ => match order.strip_prefix("locale:") {
+      Some(locale) => {
+        collator(locale)?;
+        Ok(NameOrder::Locale(locale.to_string()))
+      }
+      None => Err(format!(
+        "invalid order {:?}, expected java, bytes or locale:<tag>",
+        order
+      )),
+    },
+  }
+}
+
+fn collator(locale: &str) -> Result<Collator, String> {
+  let locale: Locale = locale
+    .parse()
+    .map_err(|e| format!("invalid locale {:?}: {}", locale, e))?;
+  Collator::try_new(&(&locale).into(), CollatorOptions::new())
+    .map_err(|e| format!("no collation for {}: {}", locale, e))
+}
+
+/// Compares like java's `String.compareTo`, by utf-16 code units. That only
+/// differs from comparing bytes for U+E000 to U+FFFF, which come after the
+/// surrogates of the supplementary planes in utf-16 but before them in utf-8.
+pub fn java_cmp(a: &[u8], b: &[u8]) -> Ordering {
+  match (str::from_utf8(a), str::from_utf8(b)) {
+    (Ok(a), Ok(b)) => a.encode_utf16().cmp(b.encode_utf16()),
+    // printing them fails anyway
+    _ => a.cmp(b),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn accepts_names_up_to_the_limit() {
+    for policy in [NamePolicy::Reject, NamePolicy::Truncate, NamePolicy::Allow] {
+      assert_eq!(checked_name_len(b"Hamburg", policy), Ok(7));
+      assert_eq!(checked_name_len(&[b'a'; MAX_STATION_NAME_LEN], policy), Ok(MAX_STATION_NAME_LEN));
+      assert_eq!(checked_name_len(b"", policy), Err(NameError::Empty));
+    }
+  }
+
+  #[test]
+  fn applies_the_policy_to_long_names() {
+    let long = "ü".repeat(MAX_STATION_NAME_LEN);
+    let long = long.as_bytes();
+    assert_eq!(checked_name_len(long, NamePolicy::Reject), Err(NameError::TooLong(long.len())));
+    assert_eq!(checked_name_len(long, NamePolicy::Allow), Ok(long.len()));
+    assert_eq!(checked_name_len(long, NamePolicy::Truncate), Ok(MAX_STATION_NAME_LEN));
+
+    // 99 ascii bytes and a two byte char, which doesn't fit
+    let straddling = format!("{}ü", "a".repeat(MAX_STATION_NAME_LEN - 1));
+    let truncated = checked_name_len(straddling.as_bytes(), NamePolicy::Truncate).unwrap();
+    assert_eq!(&straddling[..truncated], "a".repeat(MAX_STATION_NAME_LEN - 1));
+
+    let continuations = [0x80; MAX_STATION_NAME_LEN + 1];
+    assert_eq!(checked_name_len(&continuations, NamePolicy::Truncate), Err(NameError::InvalidUtf8));
+  }
+
+  #[test]
+  fn orders_like_java() {
+    // U+FF21 (fullwidth A) is one code unit, U+1F600 two starting at 0xD83D
+    let (bmp, supplementary) = ("\u{ff21}", "\u{1f600}");
+    assert_eq!(bmp.cmp(supplementary), Ordering::Less);
+    assert_eq!(java_cmp(bmp.as_bytes(), supplementary.as_bytes()), Ordering::Greater);
+    assert_eq!(java_cmp(b"Hamburg", b"Hamburg"), Ordering::Equal);
+    assert_eq!(java_cmp(b"Ha", b"Hamburg"), Ordering::Less);
+
+    let mut names = vec![bmp, "Zürich", supplementary, "Abha"];
+    NameOrder::Java.sort(&mut names);
+    assert_eq!(names, ["Abha", "Zürich", supplementary, bmp]);
+    NameOrder::Bytes.sort(&mut names);
+    assert_eq!(names, ["Abha", "Zürich", bmp, supplementary]);
+  }
+
+  #[test]
+  fn collates_for_a_locale() {
+    let mut names = vec!["Zürich", "Älmhult", "Aarhus", "Oslo"];
+    parse_name_order("locale:de").unwrap().sort(&mut names);
+    assert_eq!(names, ["Aarhus", "Älmhult", "Oslo", "Zürich"]);
+    // swedish puts å, ä and ö after z
+    parse_name_order("locale:sv").unwrap().sort(&mut names);
+    assert_eq!(names, ["Aarhus", "Oslo", "Zürich", "Älmhult"]);
+
+    assert_eq!(parse_name_order("java"), Ok(NameOrder::Java));
+    assert!(parse_name_order("german").is_err());
+    assert!(parse_name_order("locale:!!").is_err());
+  }
+
+  #[test]
+  fn normalizes_names() {
+    let apply = |form, case_fold, name: &str| {
+      let mut buffer = String::new();
+      let normalize = Normalize { form, case_fold };
+      normalize.apply(name.as_bytes(), &mut buffer).map(str::to_string)
+    };
+    let (composed, decomposed) = ("Z\u{fc}rich", "Zu\u{308}rich");
+    assert_eq!(apply(NormalForm::None, false, decomposed), None);
+    assert_eq!(apply(NormalForm::Nfc, false, decomposed).unwrap(), composed);
+    assert_eq!(apply(NormalForm::Nfc, false, composed).unwrap(), composed);
+    assert_eq!(apply(NormalForm::Nfc, true, decomposed).unwrap(), "z\u{fc}rich");
+    assert_eq!(apply(NormalForm::Nfc, false, "\u{ff28}amburg").unwrap(), "\u{ff28}amburg");
+    assert_eq!(apply(NormalForm::Nfkc, false, "\u{ff28}amburg").unwrap(), "Hamburg");
+    assert_eq!(apply(NormalForm::None, true, "Stra\u{df}e").unwrap(), "strasse");
+
+    // ascii is only ever lowercased
+    assert_eq!(apply(NormalForm::Nfkc, false, "Hamburg"), None);
+    assert_eq!(apply(NormalForm::Nfkc, true, "hamburg"), None);
+    assert_eq!(apply(NormalForm::None, true, "Hamburg").unwrap(), "hamburg");
+  }
+
+  #[test]
+  fn checks_utf8() {
+    assert_eq!(name_str("Zürich".as_bytes()), Ok("Zürich"));
+    assert_eq!(name_str(b"Z\xfcrich"), Err(NameError::InvalidUtf8));
+  }
+}
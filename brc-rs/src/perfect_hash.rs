@@ -0,0 +1,292 @@
+//! Minimal perfect hash over a known set of station names (hash and displace).
+//!
+//! Every catalog name gets its own slot in `0..len()`, found with a single
+//! probe. Each slot keeps the full 64 bit hash of its name and its first 16
+//! bytes, which hashing reads anyway. Only longer names are compared byte by
+//! byte, so a name outside the catalog whose hash collides with a catalog
+//! name's is never counted as that station.
+
+use std::{fs, io, path::Path};
+
+const MULTIPLIER: u64 = 0x9e3779b97f4a7c15;
+/// average number of names per bucket, more means a smaller table but a
+/// slower build
+const BUCKET_SIZE: usize = 4;
+const MAX_DISPLACEMENT: u32 = 1 << 20;
+
+/// bytes of a name kept in its slot
+const PREFIX_LEN: usize = 16;
+
+pub struct PerfectHash {
+  seed: u64,
+  displacements: Vec<u32>,
+  slots: Vec<Slot>,
+  names: Vec<Vec<u8>>,
+}
+
+#[derive(Clone, Copy)]
+struct Slot {
+  /// the full hash of the name
+  fingerprint: u64,
+  /// the first `PREFIX_LEN` bytes of the name as zero padded words
+  prefix: [u64; 2],
+  len: usize,
+}
+
+#[inline]
+fn mix(mut h: u64) -> u64 {
+  // murmur3 finalizer
+  h ^= h >> 33;
+  h = h.wrapping_mul(0xff51afd7ed558ccd);
+  h ^= h >> 33;
+  h = h.wrapping_mul(0xc4ceb9fe1a85ec53);
+  h ^ (h >> 33)
+}
+
+/// hash of the whole name, a word at a time, and its first `PREFIX_LEN`
+/// bytes as zero padded words
+#[inline]
+fn hash(name: &[u8], seed: u64) -> (u64, [u64; 2]) {
+  let mut h = seed ^ (name.len() as u64).wrapping_mul(MULTIPLIER);
+  let mut prefix = [0; 2];
+  let mut words = name.chunks_exact(8);
+  for (i, word) in (&mut words).enumerate() {
+    let word = u64::from_le_bytes(word.try_into().unwrap());
+    if i < prefix.len() {
+      prefix[i] = word;
+    }
+    h = (h ^ word).wrapping_mul(MULTIPLIER).rotate_left(31);
+  }
+  let rest = words.remainder();
+  let mut word = [0u8; 8];
+  word[..rest.len()].copy_from_slice(rest);
+  let word = u64::from_le_bytes(word);
+  if let Some(last) = prefix.get_mut(name.len() / 8) {
+    *last = word;
+  }
+  h = (h ^ word).wrapping_mul(MULTIPLIER);
+  (mix(h), prefix)
+}
+
+/// maps `x` uniformly into `0..n` without a division
+#[inline]
+fn reduce(x: u64, n: usize) -> usize {
+  ((x as u128 * n as u128) >> 64) as usize
+}
+
+#[inline]
+fn slot(h: u64, displacement: u32, n: usize) -> usize {
+  reduce(mix(h ^ (displacement as u64).wrapping_mul(MULTIPLIER)), n)
+}
+
+impl PerfectHash {
+  /// builds the hash over `names`, ignoring duplicates
+  pub fn new(names: &[&[u8]]) -> Self {
+    let mut names: Vec<Vec<u8>> = names.iter().map(|n| n.to_vec()).collect();
+    names.sort();
+    names.dedup();
+
+    for seed in 0.. {
+      if let Some(result) = Self::try_build(&names, seed) {
+        return result;
+      }
+    }
+    unreachable!()
+  }
+
+  /// reads station names from a catalog such as `data/weather_stations.csv`:
+  /// one station per line, anything after a `;` and `#` comment lines are
+  /// ignored
+  pub fn from_catalog(path: impl AsRef<Path>) -> io::Result<Self> {
+    let contents = fs::read(path)?;
+    let names: Vec<&[u8]> = contents
+      .split(|b| *b == b'\n')
+      .filter(|line| !line.is_empty() && !line.starts_with(b"#"))
+      .map(|line| line.split(|b| *b == b';').next().unwrap())
+      .collect();
+    Ok(Self::new(&names))
+  }
+
+  fn try_build(names: &[Vec<u8>], seed: u64) -> Option<Self> {
+    let n = names.len().max(1);
+    let num_buckets = (names.len() / BUCKET_SIZE).max(1);
+    let hashes: Vec<u64> = names.iter().map(|name| hash(name, seed).0).collect();
+
+    // names with the same hash would need the same slot
+    let mut sorted_hashes = hashes.clone();
+    sorted_hashes.sort_unstable();
+    if sorted_hashes.windows(2).any(|w| w[0] == w[1]) {
+      return None;
+    }
+
+    let mut buckets: Vec<Vec<usize>> = vec![Vec::new(); num_buckets];
+    for (i, h) in hashes.iter().enumerate() {
+      buckets[reduce(*h, num_buckets)].push(i);
+    }
+    let mut bucket_order: Vec<usize> = (0..num_buckets).collect();
+    bucket_order.sort_by_key(|b| std::cmp::Reverse(buckets[*b].len()));
+
+    // place the biggest buckets first while the table is still empty
+    let mut displacements = vec![0; num_buckets];
+    let mut slot_names: Vec<Option<usize>> = vec![None; n];
+    let mut slots = Vec::new();
+    for bucket in bucket_order {
+      let members = &buckets[bucket];
+      if members.is_empty() {
+        break;
+      }
+      let displacement = (0..MAX_DISPLACEMENT).find(|d| {
+        slots.clear();
+        for member in members {
+          let s = slot(hashes[*member], *d, n);
+          if slot_names[s].is_some() || slots.contains(&s) {
+            return false;
+          }
+          slots.push(s);
+        }
+        true
+      })?;
+      displacements[bucket] = displacement;
+      for (member, s) in members.iter().zip(&slots) {
+        slot_names[*s] = Some(*member);
+      }
+    }
+
+    let mut slots = Vec::with_capacity(n);
+    let mut slot_name_bytes = vec![Vec::new(); n];
+    for (s, member) in slot_names.iter().enumerate() {
+      slots.push(match member {
+        Some(member) => {
+          slot_name_bytes[s] = names[*member].clone();
+          let (fingerprint, prefix) = hash(&names[*member], seed);
+          Slot {
+            fingerprint,
+            prefix,
+            len: names[*member].len(),
+          }
+        }
+        // only possible for an empty catalog, make sure nothing matches
+        None => Slot {
+          fingerprint: !hash(b"", seed).0,
+          prefix: [0; 2],
+          len: 0,
+        },
+      });
+    }
+
+    Some(Self {
+      seed,
+      displacements,
+      slots,
+      names: slot_name_bytes,
+    })
+  }
+
+  /// slot of `name` if it is in the catalog
+  #[inline]
+  pub fn lookup(&self, name: &[u8]) -> Option<usize> {
+    let (h, prefix) = hash(name, self.seed);
+    let bucket = reduce(h, self.displacements.len());
+    let displacement = unsafe { *self.displacements.get_unchecked(bucket) };
+    let s = slot(h, displacement, self.slots.len());
+    let entry = unsafe { self.slots.get_unchecked(s) };
+    let found = entry.fingerprint == h
+      && entry.prefix == prefix
+      && entry.len == name.len()
+      && (name.len() <= PREFIX_LEN
+        || unsafe { self.names.get_unchecked(s) }[PREFIX_LEN..] == name[PREFIX_LEN..]);
+    found.then_some(s)
+  }
+
+  /// number of slots, the number of distinct catalog names (1 if it is empty)
+  #[allow(clippy::len_without_is_empty)]
+  pub fn len(&self) -> usize {
+    self.slots.len()
+  }
+
+  pub fn name(&self, slot: usize) -> &[u8] {
+    &self.names[slot]
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn catalog_names() -> Vec<Vec<u8>> {
+    (0..5000).map(|i| format!("station {}", i).into_bytes()).collect()
+  }
+
+  #[test]
+  fn every_name_gets_its_own_slot() {
+    let names = catalog_names();
+    let refs: Vec<&[u8]> = names.iter().map(|n| n.as_slice()).collect();
+    let perfect_hash = PerfectHash::new(&refs);
+    assert_eq!(perfect_hash.len(), names.len());
+
+    let mut seen = vec![false; names.len()];
+    for name in &names {
+      let s = perfect_hash.lookup(name).unwrap();
+      assert!(!seen[s]);
+      seen[s] = true;
+      assert_eq!(perfect_hash.name(s), name.as_slice());
+    }
+  }
+
+  #[test]
+  fn unknown_names_are_not_found() {
+    let names = catalog_names();
+    let refs: Vec<&[u8]> = names.iter().map(|n| n.as_slice()).collect();
+    let perfect_hash = PerfectHash::new(&refs);
+    for i in 5000..10000 {
+      assert_eq!(perfect_hash.lookup(format!("station {}", i).as_bytes()), None);
+    }
+    assert_eq!(perfect_hash.lookup(b""), None);
+    assert_eq!(perfect_hash.lookup(b"station 1\0"), None);
+  }
+
+  #[test]
+  fn colliding_fingerprints_are_not_found() {
+    let names: Vec<_> = ["Hamburg", "Petropavlovsk-Kamchatsky", "Petropavlovsk-Kamchatsky 2"]
+      .map(|name| name.as_bytes())
+      .to_vec();
+    // each unknown name gets the slot of the catalog name of its length,
+    // with a fingerprint that matches its hash
+    for unknown in ["Hamburh", "Petropavlovsk-Kamchatskx", "Petropavlovsk-Kamchatsky 3"] {
+      let mut perfect_hash = PerfectHash::new(&names);
+      let known = names.iter().find(|name| name.len() == unknown.len()).unwrap();
+      let known = perfect_hash.lookup(known).unwrap();
+      let (h, _) = hash(unknown.as_bytes(), perfect_hash.seed);
+      let bucket = reduce(h, perfect_hash.displacements.len());
+      let s = slot(h, perfect_hash.displacements[bucket], perfect_hash.len());
+      perfect_hash.slots.swap(s, known);
+      perfect_hash.names.swap(s, known);
+      perfect_hash.slots[s].fingerprint = h;
+      assert_eq!(perfect_hash.lookup(unknown.as_bytes()), None, "{}", unknown);
+    }
+  }
+
+  #[test]
+  fn duplicates_are_ignored() {
+    let perfect_hash = PerfectHash::new(&[b"Hamburg", b"Bulawayo", b"Hamburg"]);
+    assert_eq!(perfect_hash.len(), 2);
+  }
+
+  #[test]
+  fn empty_catalog() {
+    let perfect_hash = PerfectHash::new(&[]);
+    assert_eq!(perfect_hash.lookup(b""), None);
+    assert_eq!(perfect_hash.lookup(b"Hamburg"), None);
+  }
+
+  #[test]
+  fn weather_stations_catalog() {
+    let perfect_hash = PerfectHash::from_catalog(concat!(
+      env!("CARGO_MANIFEST_DIR"),
+      "/../data/weather_stations.csv"
+    )).unwrap();
+    assert!(perfect_hash.lookup("São Paulo".as_bytes()).is_some());
+    assert!(perfect_hash.lookup(b"Tokyo").is_some());
+    assert_eq!(perfect_hash.lookup(b"# Adapted from https"), None);
+  }
+}
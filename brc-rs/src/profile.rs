@@ -0,0 +1,216 @@
+//! Datasets for stress testing the solvers, next to the weather stations the
+//! reference generator uses.
+
+use std::{collections::HashSet, io, path::Path, thread};
+
+use rand::{Rng, SeedableRng};
+use rand_distr::Zipf;
+use rand_xoshiro::Xoshiro256PlusPlus;
+
+use crate::{
+  affinity::ThreadPlan,
+  generate::{generate, load_stations, Dataset, Picks, Selection, Station, Values},
+  MAX_STATION_NAME_LEN,
+};
+
+/// the station list shipped with the repo, usable from tests and benchmarks
+pub const REPO_WEATHER_STATIONS: &str =
+  concat!(env!("CARGO_MANIFEST_DIR"), "/../data/weather_stations.csv");
+/// length of the prefix all `SharedPrefixes` names start with
+pub const SHARED_PREFIX_LEN: usize = 64;
+pub const ZIPF_EXPONENT: f64 = 1.5;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Profile {
+  /// stations from the weather station list, like the reference generator
+  #[default]
+  Weather,
+  /// random utf-8 names of 1 to 100 bytes
+  RandomNames,
+  /// names that only differ after a long common prefix
+  SharedPrefixes,
+  /// random utf-8 names of exactly `MAX_STATION_NAME_LEN` bytes
+  MaxLengthNames,
+  /// weather stations, but every value is -99.9 or 99.9
+  ExtremeValues,
+  /// weather stations picked with a zipf distribution, the first station gets
+  /// about 40% of the rows
+  Zipf,
+}
+
+impl Profile {
+  pub const ALL: [Profile; 6] = [
+    Profile::Weather,
+    Profile::RandomNames,
+    Profile::SharedPrefixes,
+    Profile::MaxLengthNames,
+    Profile::ExtremeValues,
+    Profile::Zipf,
+  ];
+
+  /// Builds the dataset for up to `max_stations` stations. Weather stations
+  /// are read from `stations_file`, made up names are drawn from `seed`.
+  pub fn dataset(
+    self,
+    stations_file: &Path,
+    max_stations: usize,
+    seed: u64,
+  ) -> io::Result<Dataset> {
+    // a different stream than the rows, which seed their rngs per block
+    let mut rng = Xoshiro256PlusPlus::seed_from_u64(seed ^ 0x5354_4154_494f_4e53);
+    let dataset = match self {
+      Profile::Weather => Dataset::weather(load_stations(stations_file, max_stations)?),
+      Profile::RandomNames => Dataset::weather(random_stations(&mut rng, max_stations, || {
+        1..MAX_STATION_NAME_LEN + 1
+      })),
+      Profile::SharedPrefixes => Dataset::weather(shared_prefix_stations(&mut rng, max_stations)),
+      Profile::MaxLengthNames => Dataset::weather(random_stations(&mut rng, max_stations, || {
+        MAX_STATION_NAME_LEN..MAX_STATION_NAME_LEN + 1
+      })),
+      Profile::ExtremeValues => Dataset {
+        values: Values::Extremes,
+        ..Dataset::weather(load_stations(stations_file, max_stations)?)
+      },
+      Profile::Zipf => {
+        let stations = load_stations(stations_file, max_stations)?;
+        let zipf = Zipf::new(stations.len().max(1) as f64, ZIPF_EXPONENT).unwrap();
+        Dataset {
+          picks: Picks::Zipf(zipf),
+          ..Dataset::weather(stations)
+        }
+      }
+    };
+    Ok(dataset)
+  }
+}
+
+/// Generates `rows` rows of `profile` over up to `stations` stations, with
+/// the repo's station list, for tests and benchmarks.
+pub fn generate_profile(profile: Profile, stations: usize, rows: u64, seed: u64) -> Vec<u8> {
+  let dataset = profile
+    .dataset(Path::new(REPO_WEATHER_STATIONS), stations, seed)
+    .unwrap();
+  let plan = ThreadPlan {
+    threads: thread::available_parallelism().map_or(1, |n| n.get()),
+    cpus: Vec::new(),
+  };
+  let mut out = Vec::new();
+  generate(&dataset, rows, seed, &Selection::All, &plan, &mut out).unwrap();
+  out
+}
+
+/// a mean like the weather stations', which range from -10 to 20
+fn random_mean(rng: &mut impl Rng) -> f64 {
+  rng.random_range(-10.0..20.0)
+}
+
+/// Appends a random char of at most `max_len` bytes. Never `;`, a newline or
+/// any other control character, nor U+FEFF, which v10 and v15 skip as a byte
+/// order mark when it starts the file.
+fn push_random_char(rng: &mut impl Rng, max_len: usize, name: &mut String) {
+  let ranges = [0x20..0x7f, 0xa0..0x800, 0x800..0x1_0000, 0x1_0000..0x11_0000];
+  loop {
+    let range = ranges[rng.random_range(0..max_len.min(4))].clone();
+    let c = rng.random_range(range);
+    match char::from_u32(c) {
+      Some(c) if c != ';' && c != '\u{feff}' => {
+        name.push(c);
+        return;
+      }
+      // surrogates, `;` and the byte order mark
+      _ => continue,
+    }
+  }
+}
+
+/// `count` stations with distinct random names, each as many bytes as a draw
+/// from `len`
+fn random_stations(
+  rng: &mut impl Rng,
+  count: usize,
+  len: impl Fn() -> std::ops::Range<usize>,
+) -> Vec<Station> {
+  let mut seen = HashSet::new();
+  let mut stations = Vec::new();
+  while stations.len() < count {
+    let target = rng.random_range(len());
+    let mut name = String::new();
+    while name.len() < target {
+      push_random_char(rng, target - name.len(), &mut name);
+    }
+    if seen.insert(name.clone()) {
+      stations.push(Station {
+        name: name.into_bytes(),
+        mean: random_mean(rng),
+      });
+    }
+  }
+  stations
+}
+
+/// `count` stations named a shared `SHARED_PREFIX_LEN` byte prefix followed
+/// by their index
+fn shared_prefix_stations(rng: &mut impl Rng, count: usize) -> Vec<Station> {
+  let prefix: String = (0..SHARED_PREFIX_LEN)
+    .map(|_| rng.random_range(b'a'..=b'z') as char)
+    .collect();
+  (0..count)
+    .map(|i| Station {
+      name: format!("{}{}", prefix, i).into_bytes(),
+      mean: random_mean(rng),
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::MAX_STATION_NAMES;
+
+  fn names(profile: Profile) -> Vec<Vec<u8>> {
+    let dataset = profile
+      .dataset(Path::new(REPO_WEATHER_STATIONS), MAX_STATION_NAMES, 1)
+      .unwrap();
+    dataset.stations.into_iter().map(|s| s.name).collect()
+  }
+
+  #[test]
+  fn names_are_valid_and_distinct() {
+    for profile in Profile::ALL {
+      let names = names(profile);
+      assert_eq!(names.len(), MAX_STATION_NAMES, "{:?}", profile);
+      assert_eq!(names.iter().collect::<HashSet<_>>().len(), names.len());
+      for name in &names {
+        let name = std::str::from_utf8(name).unwrap();
+        assert!((1..=MAX_STATION_NAME_LEN).contains(&name.len()), "{:?}", name);
+        assert!(!name.contains([';', '\n', '\u{feff}']), "{:?}", name);
+      }
+    }
+  }
+
+  #[test]
+  fn profiles_stress_what_they_say() {
+    let lens: HashSet<_> = names(Profile::RandomNames).iter().map(|n| n.len()).collect();
+    assert_eq!(lens.len(), MAX_STATION_NAME_LEN);
+    assert!(names(Profile::MaxLengthNames).iter().all(|n| n.len() == MAX_STATION_NAME_LEN));
+    let shared = names(Profile::SharedPrefixes);
+    assert!(shared.iter().all(|n| n[..SHARED_PREFIX_LEN] == shared[0][..SHARED_PREFIX_LEN]));
+
+    let extremes = generate_profile(Profile::ExtremeValues, MAX_STATION_NAMES, 10_000, 1);
+    for line in extremes.split(|&b| b == b'\n').filter(|l| !l.is_empty()) {
+      assert!(line.ends_with(b";99.9") || line.ends_with(b";-99.9"));
+    }
+
+    let zipf = generate_profile(Profile::Zipf, MAX_STATION_NAMES, 100_000, 1);
+    let first = zipf.split(|&b| b == b'\n').filter(|l| l.starts_with(b"Tokyo;")).count();
+    assert!((30_000..50_000).contains(&first), "{}", first);
+  }
+
+  #[test]
+  fn generated_names_depend_on_the_seed() {
+    let path = Path::new(REPO_WEATHER_STATIONS);
+    let name = |seed| Profile::RandomNames.dataset(path, 1, seed).unwrap().stations[0].name.clone();
+    assert_eq!(name(1), name(1));
+    assert_ne!(name(1), name(2));
+  }
+}
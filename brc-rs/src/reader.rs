@@ -0,0 +1,183 @@
+//! How the parallel solver gets the measurements into memory.
+//!
+//! `mmap` maps the whole file, which is fastest when it fits in ram. `read`
+//! (unix only) is the bounded memory mode: every worker `pread`s the chunk it
+//! claimed into its own fixed size buffer, so peak rss only depends on the
+//! window size and the number of threads. `io-uring` (linux only, see
+//! `uring_reader`) keeps several large reads in flight from one thread while
+//! the others parse.
+
+use std::io;
+#[cfg(unix)]
+use std::{fs::File, ops::Range, os::unix::fs::FileExt, thread};
+
+use crate::MAX_STATION_NAME_LEN;
+#[cfg(unix)]
+use crate::{
+  affinity::ThreadPlan,
+  scheduler::{line_range, ChunkScheduler},
+};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ReaderKind {
+  #[default]
+  Mmap,
+  Read,
+  IoUring,
+}
+
+#[derive(Clone, Debug)]
+pub struct ReaderConfig {
+  pub kind: ReaderKind,
+  /// upper bound on read buffers plus per thread state for the `read` reader
+  pub memory_budget: usize,
+}
+
+impl Default for ReaderConfig {
+  fn default() -> Self {
+    Self {
+      kind: ReaderKind::Mmap,
+      memory_budget: 256 * 1024 * 1024,
+    }
+  }
+}
+
+/// longest measurement line with a name of at most `MAX_STATION_NAME_LEN`
+/// bytes, windows read this much past their end to finish the last line.
+/// Longer lines, like comments or names allowed past the limit, make the
+/// window read on until their newline.
+pub const MAX_LINE_LEN: usize = MAX_STATION_NAME_LEN + ";-99.9\n".len();
+
+/// rough rss of the process before reading anything (binary, libc, stacks)
+const PROCESS_OVERHEAD: usize = 4 * 1024 * 1024;
+
+/// windows smaller than this spend more time in syscalls than parsing
+const MIN_WINDOW_SIZE: usize = 64 * 1024;
+
+/// parses sizes like `4096`, `512K`, `64M` or `2G`
+pub fn parse_size(size: &str) -> Result<usize, String> {
+  let size = size.trim();
+  let (digits, multiplier) = match size.chars().last().map(|c| c.to_ascii_uppercase()) {
+    Some('K') => (&size[..size.len() - 1], 1 << 10),
+    Some('M') => (&size[..size.len() - 1], 1 << 20),
+    Some('G') => (&size[..size.len() - 1], 1 << 30),
+    _ => (size, 1),
+  };
+  digits
+    .parse::<usize>()
+    .map(|n| n * multiplier)
+    .map_err(|e| format!("invalid size {:?}: {}", size, e))
+}
+
+/// Largest window that keeps every thread's buffer plus `reserved_per_thread`
+/// bytes of other state (e.g. its hash map) under `memory_budget`, leaving
+/// some room for the rest of the process.
+pub fn window_size(
+  memory_budget: usize,
+  threads: usize,
+  reserved_per_thread: usize,
+) -> io::Result<usize> {
+  let per_thread = memory_budget.saturating_sub(PROCESS_OVERHEAD) / threads.max(1);
+  let window = per_thread
+    .saturating_sub(reserved_per_thread)
+    .saturating_sub(MAX_LINE_LEN + 1);
+  if window < MIN_WINDOW_SIZE {
+    return Err(io::Error::new(
+      io::ErrorKind::InvalidInput,
+      format!(
+        "memory budget of {} bytes is too small for {} threads, need at least {}",
+        memory_budget,
+        threads,
+        PROCESS_OVERHEAD + threads * (reserved_per_thread + MIN_WINDOW_SIZE + MAX_LINE_LEN + 1)
+      ),
+    ));
+  }
+  Ok(window)
+}
+
+/// Runs `process` over line aligned chunks of `file` from byte `start` (the
+/// start of a line) on `plan.threads` threads, each reading `window_size`
+/// bytes at a time, and returns every thread's state or the first error.
+/// `process` gets every chunk with its offset in the file. A buffer only
+/// grows past `window_size + MAX_LINE_LEN` for a line longer than
+/// `MAX_LINE_LEN`.
+#[cfg(unix)]
+pub fn read_windows<S, I, P>(
+  file: &File,
+  start: usize,
+  plan: &ThreadPlan,
+  window_size: usize,
+  init: I,
+  process: P,
+) -> io::Result<Vec<S>>
+where
+  S: Send,
+  I: Fn() -> S + Sync,
+  P: Fn(&[u8], usize, &mut S) -> io::Result<()> + Sync,
+{
+  let len = file.metadata()?.len() as usize;
+  let scheduler = ChunkScheduler::new(window_size);
+  thread::scope(|s| {
+    let threads: Vec<_> = (0..plan.threads)
+      .map(|worker| {
+        let (scheduler, init, process) = (&scheduler, &init, &process);
+        s.spawn(move || -> io::Result<S> {
+          plan.pin_worker(worker)?;
+          // one byte before the window to know if it starts on a line and
+          // enough after it to finish the last line
+          let mut buffer = Vec::with_capacity(1 + window_size + MAX_LINE_LEN);
+          let mut state = init();
+          while let Some(range) = scheduler.next_range(len.saturating_sub(start)) {
+            let range = start + range.start..start + range.end;
+            // `start` is a line start even when it doesn't follow a newline,
+            // e.g. after a byte order mark
+            let read_start = if range.start == start { start } else { range.start - 1 };
+            let mut read_end = (range.end + MAX_LINE_LEN).min(len);
+            buffer.resize(read_end - read_start, 0);
+            file.read_exact_at(&mut buffer, read_start as u64)?;
+
+            let window = range.start - read_start..range.end - read_start;
+            let mut lines = line_range(&buffer, window.clone());
+            // the last line goes on past what was read, read twice as far
+            // each time until it ends
+            while lines.start < window.end
+              && lines.end == buffer.len()
+              && buffer.last() != Some(&b'\n')
+              && read_end < len
+            {
+              let more = (buffer.len() - window.end).max(MAX_LINE_LEN);
+              let old_len = buffer.len();
+              buffer.resize(old_len + more.min(len - read_end), 0);
+              file.read_exact_at(&mut buffer[old_len..], read_end as u64)?;
+              read_end = read_start + buffer.len();
+              lines = line_range(&buffer, window.clone());
+            }
+            let offset = read_start + lines.start;
+            process(&buffer[lines], offset, &mut state)?;
+            drop_from_page_cache(file, range);
+          }
+          Ok(state)
+        })
+      })
+      .collect();
+    threads.into_iter().map(|t| t.join().unwrap()).collect()
+  })
+}
+
+/// the data won't be read again, don't let it push everything else out of
+/// the page cache
+#[cfg(target_os = "linux")]
+fn drop_from_page_cache(file: &File, range: Range<usize>) {
+  use std::os::fd::AsRawFd;
+  unsafe {
+    libc::posix_fadvise(
+      file.as_raw_fd(),
+      range.start as libc::off_t,
+      range.len() as libc::off_t,
+      libc::POSIX_FADV_DONTNEED,
+    );
+  }
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+fn drop_from_page_cache(_file: &File, _range: Range<usize>) {}
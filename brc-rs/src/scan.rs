@@ -0,0 +1,274 @@
+//! Finds every `;` and `\n` in a 64 byte block at once, using the widest simd
+//! instructions the cpu supports.
+
+pub const BLOCK_SIZE: usize = 64;
+
+/// bit `i` of each mask is set if byte `i` of the block is that separator
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct BlockMasks {
+  pub semicolons: u64,
+  pub newlines: u64,
+}
+
+pub type ScanFn = fn(&[u8; BLOCK_SIZE]) -> BlockMasks;
+
+/// pick the fastest block scanner for this cpu
+pub fn detect_scan_fn() -> ScanFn {
+  #[cfg(target_arch = "x86_64")]
+  {
+    if is_x86_feature_detected!("avx512bw") {
+      return x86::scan_block_avx512;
+    }
+    if is_x86_feature_detected!("avx2") {
+      return x86::scan_block_avx2;
+    }
+    if is_x86_feature_detected!("sse2") {
+      return x86::scan_block_sse2;
+    }
+  }
+  scan_block_scalar
+}
+
+/// portable fallback
+pub fn scan_block_scalar(block: &[u8; BLOCK_SIZE]) -> BlockMasks {
+  let mut masks = BlockMasks::default();
+  for (i, b) in block.iter().enumerate() {
+    masks.semicolons |= ((*b == b';') as u64) << i;
+    masks.newlines |= ((*b == b'\n') as u64) << i;
+  }
+  masks
+}
+
+#[cfg(target_arch = "x86_64")]
+mod x86 {
+  use std::arch::x86_64::*;
+
+  use super::{BlockMasks, BLOCK_SIZE};
+
+  // the safe wrappers are only handed out by `detect_scan_fn` after checking
+  // that the cpu supports the instructions
+
+  pub fn scan_block_sse2(block: &[u8; BLOCK_SIZE]) -> BlockMasks {
+    unsafe { scan_block_sse2_impl(block) }
+  }
+
+  pub fn scan_block_avx2(block: &[u8; BLOCK_SIZE]) -> BlockMasks {
+    unsafe { scan_block_avx2_impl(block) }
+  }
+
+  pub fn scan_block_avx512(block: &[u8; BLOCK_SIZE]) -> BlockMasks {
+    unsafe { scan_block_avx512_impl(block) }
+  }
+
+  #[target_feature(enable = "sse2")]
+  unsafe fn scan_block_sse2_impl(block: &[u8; BLOCK_SIZE]) -> BlockMasks {
+    let semicolon = _mm_set1_epi8(b';' as i8);
+    let newline = _mm_set1_epi8(b'\n' as i8);
+    let mut masks = BlockMasks::default();
+    for i in 0..BLOCK_SIZE / 16 {
+      let chunk = _mm_loadu_si128(block.as_ptr().add(16 * i) as *const __m128i);
+      let semicolons = _mm_movemask_epi8(_mm_cmpeq_epi8(chunk, semicolon));
+      let newlines = _mm_movemask_epi8(_mm_cmpeq_epi8(chunk, newline));
+      masks.semicolons |= (semicolons as u16 as u64) << (16 * i);
+      masks.newlines |= (newlines as u16 as u64) << (16 * i);
+    }
+    masks
+  }
+
+  #[target_feature(enable = "avx2")]
+  unsafe fn scan_block_avx2_impl(block: &[u8; BLOCK_SIZE]) -> BlockMasks {
+    let semicolon = _mm256_set1_epi8(b';' as i8);
+    let newline = _mm256_set1_epi8(b'\n' as i8);
+    let lo = _mm256_loadu_si256(block.as_ptr() as *const __m256i);
+    let hi = _mm256_loadu_si256(block.as_ptr().add(32) as *const __m256i);
+    let lo_semicolons = _mm256_movemask_epi8(_mm256_cmpeq_epi8(lo, semicolon));
+    let hi_semicolons = _mm256_movemask_epi8(_mm256_cmpeq_epi8(hi, semicolon));
+    let lo_newlines = _mm256_movemask_epi8(_mm256_cmpeq_epi8(lo, newline));
+    let hi_newlines = _mm256_movemask_epi8(_mm256_cmpeq_epi8(hi, newline));
+    BlockMasks {
+      semicolons: lo_semicolons as u32 as u64 | ((hi_semicolons as u32 as u64) << 32),
+      newlines: lo_newlines as u32 as u64 | ((hi_newlines as u32 as u64) << 32),
+    }
+  }
+
+  #[target_feature(enable = "avx512bw")]
+  unsafe fn scan_block_avx512_impl(block: &[u8; BLOCK_SIZE]) -> BlockMasks {
+    let chunk = _mm512_loadu_si512(block.as_ptr() as *const __m512i);
+    BlockMasks {
+      semicolons: _mm512_cmpeq_epi8_mask(chunk, _mm512_set1_epi8(b';' as i8)),
+      newlines: _mm512_cmpeq_epi8_mask(chunk, _mm512_set1_epi8(b'\n' as i8)),
+    }
+  }
+}
+
+/// Iterates over the `(semicolon, newline)` indices of every line in a buffer.
+///
+/// Lines alternate between exactly one `;` and one `\n`, so once one mask of
+/// the current block runs out nothing in the other mask is left to visit and
+/// the next block can be scanned. A missing final newline is reported as
+/// `buffer.len()`.
+pub struct LineSplitter<'a> {
+  buffer: &'a [u8],
+  scan: ScanFn,
+  block_start: usize,
+  masks: BlockMasks,
+}
+
+impl<'a> LineSplitter<'a> {
+  pub fn new(buffer: &'a [u8], scan: ScanFn) -> Self {
+    let mut result = Self {
+      buffer,
+      scan,
+      block_start: 0,
+      masks: BlockMasks::default(),
+    };
+    result.masks = result.scan_block();
+    result
+  }
+
+  fn scan_block(&self) -> BlockMasks {
+    match self.buffer.get(self.block_start..self.block_start + BLOCK_SIZE) {
+      Some(block) => (self.scan)(block.try_into().unwrap()),
+      None => {
+        // pad the last partial block with zeros, which never match
+        let mut block = [0u8; BLOCK_SIZE];
+        let tail = &self.buffer[self.block_start.min(self.buffer.len())..];
+        block[..tail.len()].copy_from_slice(tail);
+        (self.scan)(&block)
+      }
+    }
+  }
+
+  fn next_block(&mut self) -> bool {
+    self.block_start += BLOCK_SIZE;
+    if self.block_start >= self.buffer.len() {
+      return false;
+    }
+    self.masks = self.scan_block();
+    true
+  }
+
+  #[inline]
+  fn next_semicolon(&mut self) -> Option<usize> {
+    while self.masks.semicolons == 0 {
+      if !self.next_block() {
+        return None;
+      }
+    }
+    let ind = self.masks.semicolons.trailing_zeros() as usize;
+    self.masks.semicolons &= self.masks.semicolons - 1;
+    Some(self.block_start + ind)
+  }
+
+  #[inline]
+  fn next_newline(&mut self) -> Option<usize> {
+    while self.masks.newlines == 0 {
+      if !self.next_block() {
+        return None;
+      }
+    }
+    let ind = self.masks.newlines.trailing_zeros() as usize;
+    self.masks.newlines &= self.masks.newlines - 1;
+    Some(self.block_start + ind)
+  }
+}
+
+impl Iterator for LineSplitter<'_> {
+  type Item = (usize, usize);
+
+  #[inline]
+  fn next(&mut self) -> Option<Self::Item> {
+    let semicolon = self.next_semicolon()?;
+    let newline = self.next_newline().unwrap_or(self.buffer.len());
+    Some((semicolon, newline))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn scan_fns() -> Vec<(&'static str, ScanFn)> {
+    let mut result: Vec<(&'static str, ScanFn)> = vec![("scalar", scan_block_scalar)];
+    #[cfg(target_arch = "x86_64")]
+    {
+      if is_x86_feature_detected!("sse2") {
+        result.push(("sse2", x86::scan_block_sse2));
+      }
+      if is_x86_feature_detected!("avx2") {
+        result.push(("avx2", x86::scan_block_avx2));
+      }
+      if is_x86_feature_detected!("avx512bw") {
+        result.push(("avx512", x86::scan_block_avx512));
+      }
+    }
+    result
+  }
+
+  /// deterministic pseudo random blocks heavy in separators
+  fn test_blocks() -> Vec<[u8; BLOCK_SIZE]> {
+    let alphabet = b";\nab;\n\xc3\xbc9.-";
+    let mut state = 0x9e3779b97f4a7c15u64;
+    let mut blocks = vec![[0u8; BLOCK_SIZE], [b';'; BLOCK_SIZE], [b'\n'; BLOCK_SIZE]];
+    for _ in 0..1000 {
+      let mut block = [0u8; BLOCK_SIZE];
+      for b in block.iter_mut() {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        *b = alphabet[(state % alphabet.len() as u64) as usize];
+      }
+      blocks.push(block);
+    }
+    blocks
+  }
+
+  #[test]
+  fn simd_scanners_match_scalar() {
+    for block in test_blocks() {
+      let expected = scan_block_scalar(&block);
+      for (name, scan) in scan_fns() {
+        assert_eq!(scan(&block), expected, "{} on {:?}", name, block);
+      }
+    }
+  }
+
+  #[test]
+  fn scalar_scanner_sets_the_right_bits() {
+    let mut block = [b'a'; BLOCK_SIZE];
+    block[0] = b';';
+    block[5] = b'\n';
+    block[63] = b';';
+    assert_eq!(
+      scan_block_scalar(&block),
+      BlockMasks {
+        semicolons: 1 | (1 << 63),
+        newlines: 1 << 5,
+      }
+    );
+  }
+
+  #[test]
+  fn splits_lines_across_blocks() {
+    let mut buffer = Vec::new();
+    let mut expected = Vec::new();
+    for i in 0..200 {
+      let name = "x".repeat(i % 100 + 1);
+      buffer.extend_from_slice(name.as_bytes());
+      let semicolon = buffer.len();
+      buffer.extend_from_slice(b";-12.3\n");
+      expected.push((semicolon, buffer.len() - 1));
+    }
+    for (name, scan) in scan_fns() {
+      let lines: Vec<_> = LineSplitter::new(&buffer, scan).collect();
+      assert_eq!(lines, expected, "{}", name);
+    }
+  }
+
+  #[test]
+  fn missing_final_newline() {
+    let buffer = b"Hamburg;12.0\nBulawayo;8.9";
+    let lines: Vec<_> = LineSplitter::new(buffer, detect_scan_fn()).collect();
+    assert_eq!(lines, vec![(7, 12), (21, buffer.len())]);
+  }
+}
@@ -0,0 +1,74 @@
+//! Hands out small line aligned chunks of a buffer to whichever thread asks
+//! next, so a slow core only holds up the run by one chunk instead of a whole
+//! `1 / num_threads` segment.
+
+use std::{
+  ops::Range,
+  sync::atomic::{AtomicUsize, Ordering},
+};
+
+use memchr::memchr;
+
+pub const DEFAULT_CHUNK_SIZE: usize = 4 * 1024 * 1024;
+
+pub struct ChunkScheduler {
+  cursor: AtomicUsize,
+  chunk_size: usize,
+}
+
+/// index just past the first newline at or after `ind`
+fn next_line_start(buffer: &[u8], ind: usize) -> usize {
+  if ind >= buffer.len() {
+    return buffer.len();
+  }
+  match memchr(b'\n', &buffer[ind..]) {
+    Some(newline_ind) => ind + newline_ind + 1,
+    None => buffer.len(),
+  }
+}
+
+impl ChunkScheduler {
+  pub fn new(chunk_size: usize) -> Self {
+    Self {
+      cursor: AtomicUsize::new(0),
+      chunk_size: chunk_size.max(1),
+    }
+  }
+
+  /// Claims the next chunk of `buffer`, or `None` once everything has been
+  /// handed out.
+  ///
+  /// Chunks are cut at fixed offsets and a line belongs to the chunk its
+  /// first byte is in, so chunks are disjoint, cover every line and threads
+  /// don't need to agree on anything but the cursor.
+  pub fn next_chunk(&self, buffer: &[u8]) -> Option<Range<usize>> {
+    let range = self.next_range(buffer.len())?;
+    Some(line_range(buffer, range))
+  }
+
+  /// how far into the input chunks have been handed out
+  pub fn position(&self) -> usize {
+    self.cursor.load(Ordering::Relaxed)
+  }
+
+  /// claims the next fixed size `start..end` range of an input of length
+  /// `len`, without looking at the data
+  pub fn next_range(&self, len: usize) -> Option<Range<usize>> {
+    let start = self.cursor.fetch_add(self.chunk_size, Ordering::Relaxed);
+    if start >= len {
+      return None;
+    }
+    Some(start..start.saturating_add(self.chunk_size).min(len))
+  }
+}
+
+/// the lines of `buffer` whose first byte is in `range`
+pub fn line_range(buffer: &[u8], range: Range<usize>) -> Range<usize> {
+  let line_start = if range.start == 0 {
+    0
+  } else {
+    next_line_start(buffer, range.start - 1)
+  };
+  let line_end = next_line_start(buffer, range.end - 1);
+  line_start..line_end
+}
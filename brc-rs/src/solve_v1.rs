@@ -1,30 +1,34 @@
-use std::{collections::HashMap, fs};
+use std::{collections::HashMap, fs, path::Path};
 
 use ordered_float::NotNan;
 
-use crate::write_string_to_output;
+use crate::names::{checked_name_len, NameConfig};
 
 /// Simplest possible solution
-pub fn solve_v1()  {
+pub fn solve_v1(input: &Path, names: &NameConfig) -> String {
   let mut station_values: HashMap<String, Vec<NotNan<f32>>> = HashMap::new();
+  let mut name_buffer = String::new();
   println!("Reading...");
-  let file_contents = fs::read_to_string("../measurements.txt").unwrap();
+  let file_contents = fs::read_to_string(input).unwrap();
   println!("Calculating...");
   for line in file_contents.split("\n") {
     let components: Vec<_> = line.split(";").collect();
     if components.len() != 2 {
       break;
     }
+    let name = components[0];
+    let name = names.normalize.apply(name.as_bytes(), &mut name_buffer).unwrap_or(name);
+    let name = &name[..checked_name_len(name.as_bytes(), names.policy).unwrap()];
     station_values
-      .entry(components[0].to_string())
-      .or_insert(Vec::new())
+      .entry(name.to_string())
+      .or_default()
       .push(components[1].parse().unwrap());
   }
   println!("Summing");
   let mut station_keys: Vec<_> = station_values.keys().collect();
-  station_keys.sort();
+  names.order.sort(&mut station_keys);
   let mut result = String::new();
-  result.push_str("{");
+  result.push('{');
   for (i, key) in station_keys.into_iter().enumerate() {
     let values = station_values.get(key).unwrap();
     let min = values.iter().min().unwrap();
@@ -36,5 +40,5 @@ pub fn solve_v1()  {
     result.push_str(&format!("{}={:.1}/{:.1}/{:.1}", key, min, avg, max));
   }
   result.push_str("}\n");
-  write_string_to_output(&result);
+  result
 }
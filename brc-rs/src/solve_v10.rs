@@ -1,16 +1,22 @@
-use std::fs::File;
+use std::{fmt, fs::File, path::Path};
 
-use memchr::memchr;
-use memmap2::{Mmap, MmapOptions};
+use memchr::{memchr, memchr2};
+use memmap2::MmapOptions;
 
-use crate::{write_string_to_output, MAX_STATION_NAMES, MAX_STATION_NAME_LEN, MEASUREMENTS};
+use crate::{
+  format::InputFormat,
+  input::Input,
+  names::{checked_name_len, name_str, NameConfig, NameError},
+};
 
 #[derive(Copy, Clone, Debug)]
-struct Record {
-  total: i32,
-  min: i32,
-  max: i32,
-  num: usize,
+pub struct Record {
+  pub total: i64,
+  pub min: i32,
+  pub max: i32,
+  pub num: usize,
+  /// rows with a missing value, which aren't in the others
+  pub missing: usize,
 }
 
 impl Record {
@@ -20,84 +26,91 @@ impl Record {
       min: i32::MAX,
       max: i32::MIN,
       num: 0,
+      missing: 0,
     }
   }
 }
 
-struct RawBufReader {
-  current_ind: usize,
-  mmap: Mmap,
+/// what's wrong with the input, and the byte it starts at
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParseError {
+  /// a line without a `;`
+  MissingSemicolon(usize),
+  /// a name that's empty, too long or not utf-8
+  InvalidName(usize, NameError),
+  /// a temperature that isn't one to three digits, a dot and a digit,
+  /// followed by a newline or the end of the input, or that isn't in the
+  /// input's number format
+  InvalidTemperature(usize),
+  /// more stations or name bytes than the map has room for
+  MapFull(usize),
 }
 
-impl RawBufReader {
-  fn new(file: File) -> Self {
-    Self {
-      mmap: unsafe { MmapOptions::new().map(&file).unwrap() },
-      current_ind: 0,
+impl fmt::Display for ParseError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      ParseError::MissingSemicolon(at) => write!(f, "missing ';' in line at byte {}", at),
+      ParseError::InvalidName(at, e) => write!(f, "{} at byte {}", e, at),
+      ParseError::InvalidTemperature(at) => write!(f, "invalid temperature at byte {}", at),
+      ParseError::MapFull(at) => write!(f, "too many stations at byte {}", at),
     }
   }
+}
 
-  fn has_next_line(&self) -> bool {
-    self.mmap.len() > self.current_ind
-  }
+impl std::error::Error for ParseError {}
 
-  #[inline]
-  fn next_char(&mut self) -> u8 {
-    // check if should read
-    let result = self.mmap[self.current_ind];
-    self.current_ind += 1;
-    result
-  }
+/// The mean of `num` temperatures adding up to `total` tenths, with one
+/// decimal. Rounded in fixed point with halves away from zero, which is what
+/// the f32 means of the other versions print, `-0.0` included.
+pub fn format_mean(total: i64, num: usize) -> String {
+  let (total, num) = (total as i128, num as i128);
+  let tenths = (2 * total.abs() + num) / (2 * num);
+  let sign = if total < 0 { "-" } else { "" };
+  format!("{}{}.{}", sign, tenths / 10, tenths % 10)
 }
 
-fn parse_temperature(reader: &mut RawBufReader) -> i32 {
-  let is_negative = reader.mmap[reader.current_ind] == b'-';
-  if is_negative {
-    reader.current_ind += 1;
-  }
-
-  let (a, b, c, d, increment) = if reader.mmap[reader.current_ind + 1] == b'.' {
-    (
-      0,
-      0,
-      reader.mmap[reader.current_ind] - b'0',
-      reader.mmap[reader.current_ind + 2] - b'0',
-      4,
-    )
-  } else if reader.mmap[reader.current_ind + 2] == b'.' {
-    (
-      0,
-      reader.mmap[reader.current_ind] - b'0',
-      reader.mmap[reader.current_ind + 1] - b'0',
-      reader.mmap[reader.current_ind + 3] - b'0',
-      5,
-    )
+/// Parses the temperature at `ind` and the newline after it. Returns the
+/// temperature in tenths and the start of the next line.
+fn parse_temperature(bytes: &[u8], ind: usize) -> Result<(i32, usize), ParseError> {
+  let is_negative = bytes.get(ind) == Some(&b'-');
+  let start = ind + is_negative as usize;
+
+  let digits = |at: &[usize]| {
+    at.iter().try_fold(0, |value, &i| match bytes.get(i) {
+      Some(b) if b.is_ascii_digit() => Some(10 * value + (b - b'0') as i32),
+      _ => None,
+    })
+  };
+  let (temperature_value, end) = if bytes.get(start + 1) == Some(&b'.') {
+    (digits(&[start, start + 2]), start + 3)
+  } else if bytes.get(start + 2) == Some(&b'.') {
+    (digits(&[start, start + 1, start + 3]), start + 4)
+  } else if bytes.get(start + 3) == Some(&b'.') {
+    (digits(&[start, start + 1, start + 2, start + 4]), start + 5)
   } else {
-    (
-      reader.mmap[reader.current_ind] - b'0',
-      reader.mmap[reader.current_ind + 1] - b'0',
-      reader.mmap[reader.current_ind + 2] - b'0',
-      reader.mmap[reader.current_ind + 4] - b'0',
-      6,
-    )
+    (None, start)
+  };
+  let temperature_value = temperature_value.ok_or(ParseError::InvalidTemperature(ind))?;
+  let next_line = match bytes.get(end) {
+    Some(b'\n') => end + 1,
+    None => end,
+    Some(_) => return Err(ParseError::InvalidTemperature(ind)),
   };
-  let temperature_value: i32 = 1000 * a as i32 + 100 * b as i32 + 10 * c as i32 + d as i32;
-  reader.current_ind += increment;
 
   if is_negative {
-    -temperature_value
+    Ok((-temperature_value, next_line))
   } else {
-    temperature_value
+    Ok((temperature_value, next_line))
   }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug)]
 struct MapStrRef {
   start: usize,
   end: usize,
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug)]
 struct MapKvPair {
   key: MapStrRef,
   value: Record,
@@ -110,52 +123,71 @@ impl MapKvPair {
       value: Record::new(),
     }
   }
+
+  fn is_empty(&self) -> bool {
+    self.key.start == 0 && self.key.end == 0
+  }
 }
 
-const MAP_NAME_SIZE: usize = 1048576;
-const MAP_ENTRIES: usize = 16384;
+pub const MAP_NAME_SIZE: usize = 1048576;
+pub const MAP_ENTRIES: usize = 16384;
 
 /// fnv a hash
 fn fnv_hash(value: &[u8]) -> usize {
   let l = value.len().min(8);
   let mut conv_key = 0;
-  for i in 0..l {
-    conv_key |= (value[i] as u64) << (8*i);
+  for (i, b) in value[..l].iter().enumerate() {
+    conv_key |= (*b as u64) << (8 * i);
   }
   conv_key ^= value.len() as u64;
-  (conv_key * 16381) as usize
+  conv_key.wrapping_mul(16381) as usize
 }
 
-struct FixedSizeMap {
-  names: [u8; MAP_NAME_SIZE],
-  entries: [MapKvPair; MAP_ENTRIES],
+/// boxed so it also fits on small thread stacks
+pub struct FixedSizeMap {
+  names: Box<[u8; MAP_NAME_SIZE]>,
+  entries: Box<[MapKvPair; MAP_ENTRIES]>,
   last_name_idx: usize,
+  len: usize,
+}
+
+impl Default for FixedSizeMap {
+  fn default() -> Self {
+    Self::new()
+  }
 }
 
 impl FixedSizeMap {
-  fn new() -> Self {
+  pub fn new() -> Self {
     Self {
-      names: [0; MAP_NAME_SIZE],
-      entries: [MapKvPair::new(); MAP_ENTRIES],
+      names: vec![0; MAP_NAME_SIZE].try_into().unwrap(),
+      entries: vec![MapKvPair::new(); MAP_ENTRIES].try_into().unwrap(),
       last_name_idx: 0,
+      len: 0,
     }
   }
 
-  fn get_or_insert(&mut self, name: &[u8]) -> usize {
+  /// The record for `name`, which must not be empty, inserting it if it's
+  /// new. `None` if there's no room left for it.
+  pub fn get_or_insert(&mut self, name: &[u8]) -> Option<&mut Record> {
     let hash = fnv_hash(name);
     let mut idx = hash % self.entries.len();
     loop {
+      // idx is always reduced mod entries.len()
       let cur_entry = unsafe { self.entries.get_unchecked_mut(idx) };
-      if cur_entry.key.start == 0 && cur_entry.key.end == 0 {
+      if cur_entry.is_empty() {
         let name_start = self.last_name_idx;
         let name_end = self.last_name_idx + name.len();
+        // keep one entry empty so probing for a missing name ends
+        if self.len + 1 == MAP_ENTRIES || name_end > MAP_NAME_SIZE {
+          return None;
+        }
         cur_entry.key.start = name_start;
         cur_entry.key.end = name_end;
-        for i in 0..name.len() {
-          self.names[i + name_start] = name[i];
-        }
+        self.names[name_start..name_end].copy_from_slice(name);
         self.last_name_idx = name_end;
-        return idx;
+        self.len += 1;
+        break;
       }
       let entry = &self.entries[idx];
 
@@ -165,15 +197,19 @@ impl FixedSizeMap {
         continue;
       }
 
-      return idx;
+      break;
     }
+    Some(&mut self.entries[idx].value)
   }
 
-  fn get(&self, name: &[u8]) -> Record {
+  pub fn get(&self, name: &[u8]) -> Option<Record> {
     let hash = fnv_hash(name);
     let mut idx = hash % self.entries.len();
     loop {
       let entry = &self.entries[idx];
+      if entry.is_empty() {
+        return None;
+      }
 
       // linear probing
       if &self.names[entry.key.start..entry.key.end] != name {
@@ -181,11 +217,11 @@ impl FixedSizeMap {
         continue;
       }
 
-      return entry.value;
+      return Some(entry.value);
     }
   }
 
-  fn keys(&self) -> Vec<&[u8]> {
+  pub fn keys(&self) -> Vec<&[u8]> {
     self
       .entries
       .iter()
@@ -195,52 +231,276 @@ impl FixedSizeMap {
   }
 }
 
-/// use memchr (avx)
-pub fn solve_v10() {
-  let mut station_values = FixedSizeMap::new();
-  let file = File::open(MEASUREMENTS).unwrap();
-  let mut bufreader = RawBufReader::new(file);
-
-  println!("Reading");
-
-  while bufreader.has_next_line() {
+/// Adds every line of `bytes` to `station_values`, after the header and
+/// without comments. Stops at the first line that isn't `name;temperature`,
+/// with whitespace around both if trimming, or `name;` and a missing value.
+pub fn parse(
+  bytes: &[u8],
+  names: &NameConfig,
+  format: &InputFormat,
+  station_values: &mut FixedSizeMap,
+) -> Result<(), ParseError> {
+  let mut name_buffer = String::new();
+  let mut current_ind = format.data_start(bytes);
+  while current_ind < bytes.len() {
+    if format.is_comment(&bytes[current_ind..]) {
+      current_ind = match memchr(b'\n', &bytes[current_ind..]) {
+        Some(newline_ind) => current_ind + newline_ind + 1,
+        None => bytes.len(),
+      };
+      continue;
+    }
     // first character should be non newline + city name
-    let station_start_ind = bufreader.current_ind;
-    let semicolon_ind = memchr(b';', &bufreader.mmap[bufreader.current_ind..]).unwrap();
-    let station_end_ind = semicolon_ind + station_start_ind;
-    bufreader.current_ind += semicolon_ind + 1;
+    let station_start_ind = current_ind;
+    let station_end_ind = match memchr2(b';', b'\n', &bytes[current_ind..]) {
+      Some(semicolon_ind) if bytes[current_ind + semicolon_ind] == b';' => {
+        current_ind + semicolon_ind
+      }
+      _ => return Err(ParseError::MissingSemicolon(station_start_ind)),
+    };
+    let station_name = &bytes[format.field(bytes, station_start_ind..station_end_ind)];
+    let station_name =
+      names.normalize.apply(station_name, &mut name_buffer).map_or(station_name, str::as_bytes);
+    let name_len = checked_name_len(station_name, names.policy)
+      .map_err(|e| ParseError::InvalidName(station_start_ind, e))?;
 
-    let value = parse_temperature(&mut bufreader);
+    let value_start = station_end_ind + 1;
+    let (value, next_line) =
+      if format.trim || !format.number.is_plain() || format.has_missing_values() {
+        let line_end =
+          memchr(b'\n', &bytes[value_start..]).map_or(bytes.len(), |ind| value_start + ind);
+        let value_field = format.field(bytes, value_start..line_end);
+        let value = if format.is_missing(&bytes[value_field.clone()]) {
+          None
+        } else {
+          let value = format.number.parse_tenths(&bytes[value_field.clone()]);
+          Some(value.ok_or(ParseError::InvalidTemperature(value_field.start))?)
+        };
+        (value.filter(|value| !format.is_sentinel(*value)), line_end + 1)
+      } else {
+        let (value, next_line) = parse_temperature(bytes, value_start)?;
+        (Some(value), next_line)
+      };
+    current_ind = next_line;
 
-    let station_name = &bufreader.mmap[station_start_ind..station_end_ind];
-    let value_idx = station_values.get_or_insert(station_name);
-    let value_entry = &mut station_values.entries[value_idx].value;
-    value_entry.total += value;
+    let station_name = &station_name[..name_len];
+    let value_entry = station_values
+      .get_or_insert(station_name)
+      .ok_or(ParseError::MapFull(station_start_ind))?;
+    // names are only checked to be utf-8 on their station's first row
+    if value_entry.num == 0 && value_entry.missing == 0 {
+      name_str(station_name).map_err(|e| ParseError::InvalidName(station_start_ind, e))?;
+    }
+    let Some(value) = value else {
+      value_entry.missing += 1;
+      continue;
+    };
+    value_entry.total += value as i64;
     value_entry.min = value_entry.min.min(value);
     value_entry.max = value_entry.max.max(value);
     value_entry.num += 1;
   }
+  Ok(())
+}
+
+/// use memchr (avx)
+pub fn solve_v10(input: &Path, names: &NameConfig, format: &InputFormat) -> String {
+  let file = File::open(input).unwrap();
+  let mmap = unsafe { MmapOptions::new().map(&file).unwrap() };
+  solve_v10_input(&mmap, names, format)
+}
+
+/// `solve_v10` on anything already in memory
+pub fn solve_v10_input(
+  input: &(impl Input + ?Sized),
+  names: &NameConfig,
+  format: &InputFormat,
+) -> String {
+  let mut station_values = FixedSizeMap::new();
+
+  println!("Reading");
+  parse(input.bytes(), names, format, &mut station_values).unwrap();
+
   println!("Summing");
   let mut station_keys: Vec<_> = station_values.keys();
-  station_keys.sort();
+  names.order.sort(&mut station_keys);
   let mut result = String::new();
-  result.push_str("{");
+  result.push('{');
   for (i, key) in station_keys.iter().enumerate() {
-    let values = station_values.get(key);
-    let min = values.min as f32 / 10.0;
-    let avg = values.total as f32 / 10.0 / values.num as f32;
-    let max = values.max as f32 / 10.0;
+    let values = station_values.get(key).unwrap();
     if i != 0 {
       result.push_str(", ");
     }
-    result.push_str(&format!(
-      "{}={:.1}/{:.1}/{:.1}",
-      std::str::from_utf8(key).unwrap(),
-      min,
-      avg,
-      max
-    ));
+    // checked on the station's first row
+    result.push_str(name_str(key).unwrap());
+    // stations with only missing values have nothing to take min/mean/max of
+    if values.num == 0 {
+      result.push_str("=NA/NA/NA");
+    } else {
+      // temperatures with thousands separators have more digits than an f32
+      let min = values.min as f64 / 10.0;
+      let max = values.max as f64 / 10.0;
+      let mean = format_mean(values.total, values.num);
+      result.push_str(&format!("={:.1}/{}/{:.1}", min, mean, max));
+    }
+    if format.has_missing_values() {
+      result.push_str(&format!("/{}", values.missing));
+    }
   }
   result.push_str("}\n");
-  write_string_to_output(&result);
+  result
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::format::{DecimalSeparator, NumberFormat};
+
+  fn parse_all(input: &[u8]) -> Result<FixedSizeMap, ParseError> {
+    parse_format(input, &InputFormat::default())
+  }
+
+  fn parse_format(input: &[u8], format: &InputFormat) -> Result<FixedSizeMap, ParseError> {
+    let mut map = FixedSizeMap::new();
+    parse(input, &NameConfig::default(), format, &mut map).map(|()| map)
+  }
+
+  #[test]
+  fn parses_lines() {
+    let map = parse_all(b"Hamburg;12.0\nBulawayo;8.9\nHamburg;-3.4\nPalembang;100.5").unwrap();
+    let hamburg = map.get(b"Hamburg").unwrap();
+    assert_eq!((hamburg.min, hamburg.max, hamburg.total, hamburg.num), (-34, 120, 86, 2));
+    assert_eq!(map.get(b"Palembang").unwrap().max, 1005);
+    assert!(map.get(b"Oslo").is_none());
+    assert_eq!(map.keys().len(), 3);
+  }
+
+  #[test]
+  fn skips_headers_and_comments() {
+    let input = b"station;temperature\n# a comment\nHamburg;12.0\n#Oslo;1.0\nHamburg;-3.4\n#";
+    let format = InputFormat {
+      comment_prefix: Some("#".to_string()),
+      header_lines: 1,
+      ..Default::default()
+    };
+    let map = parse_format(input, &format).unwrap();
+    let hamburg = map.get(b"Hamburg").unwrap();
+    assert_eq!((hamburg.min, hamburg.max, hamburg.total, hamburg.num), (-34, 120, 86, 2));
+    assert_eq!(map.keys().len(), 1);
+
+    // still lines of their own without the options
+    assert_eq!(parse_all(input).err(), Some(ParseError::InvalidTemperature(8)));
+    let format = InputFormat {
+      header_lines: 1,
+      ..Default::default()
+    };
+    assert_eq!(parse_format(input, &format).err(), Some(ParseError::MissingSemicolon(20)));
+  }
+
+  #[test]
+  fn strips_the_byte_order_mark_and_trims() {
+    let input = b"\xef\xbb\xbfHamburg;12.0\n Hamburg\t; -3.4 \r\nOslo;1.0";
+    assert_eq!(parse_all(input).err(), Some(ParseError::InvalidTemperature(26)));
+    let trim = InputFormat {
+      trim: true,
+      ..Default::default()
+    };
+    let map = parse_format(input, &trim).unwrap();
+    let hamburg = map.get(b"Hamburg").unwrap();
+    assert_eq!((hamburg.min, hamburg.max, hamburg.total, hamburg.num), (-34, 120, 86, 2));
+    assert_eq!(map.keys().len(), 2);
+
+    let invalid_temperature = |ind| Some(ParseError::InvalidTemperature(ind));
+    assert_eq!(parse_format(b"Hamburg; 1 2.0", &trim).err(), invalid_temperature(9));
+    assert_eq!(parse_format(b"Hamburg;  \n", &trim).err(), invalid_temperature(10));
+    let empty_name = Some(ParseError::InvalidName(0, NameError::Empty));
+    assert_eq!(parse_format(b" ;1.0", &trim).err(), empty_name);
+  }
+
+  #[test]
+  fn parses_number_formats() {
+    let format = InputFormat {
+      number: NumberFormat::new(DecimalSeparator::Comma, Some(b'.')).unwrap(),
+      ..Default::default()
+    };
+    let map = parse_format(b"Hamburg;12,0\nHamburg;-1.234,5\nOslo;1,0", &format).unwrap();
+    let hamburg = map.get(b"Hamburg").unwrap();
+    assert_eq!((hamburg.min, hamburg.max, hamburg.total, hamburg.num), (-12345, 120, -12225, 2));
+
+    let invalid_temperature = |ind| Some(ParseError::InvalidTemperature(ind));
+    assert_eq!(parse_format(b"Hamburg;12.0", &format).err(), invalid_temperature(8));
+    assert_eq!(parse_format(b"Hamburg;12,0\r\n", &format).err(), invalid_temperature(8));
+    let trim = InputFormat { trim: true, ..format };
+    assert!(parse_format(b"Hamburg; 12,0\r\n", &trim).is_ok());
+  }
+
+  #[test]
+  fn counts_missing_values() {
+    let format = InputFormat {
+      missing: vec!["NA".to_string(), String::new()],
+      sentinels: vec![-9999],
+      ..Default::default()
+    };
+    let input = b"Hamburg;12.0\nHamburg;NA\nHamburg;-999.9\nOslo;\nHamburg;-3.4";
+    let map = parse_format(input, &format).unwrap();
+    let hamburg = map.get(b"Hamburg").unwrap();
+    assert_eq!((hamburg.min, hamburg.max, hamburg.total, hamburg.num), (-34, 120, 86, 2));
+    assert_eq!(hamburg.missing, 2);
+    let oslo = map.get(b"Oslo").unwrap();
+    assert_eq!((oslo.num, oslo.missing), (0, 1));
+    let output = solve_v10_input(&input[..], &NameConfig::default(), &format);
+    assert_eq!(output, "{Hamburg=-3.4/4.3/12.0/2, Oslo=NA/NA/NA/1}\n");
+
+    let invalid_temperature = Some(ParseError::InvalidTemperature(8));
+    assert_eq!(parse_format(b"Hamburg;N/A\n", &format).err(), invalid_temperature);
+    assert_eq!(parse_format(b"Hamburg;NA\n", &InputFormat::default()).err(), invalid_temperature);
+    let trim = InputFormat { trim: true, ..format };
+    let map = parse_format(b"Hamburg; NA \r\nHamburg;  \r\n", &trim).unwrap();
+    assert_eq!(map.get(b"Hamburg").unwrap().missing, 2);
+  }
+
+  #[test]
+  fn rejects_malformed_lines() {
+    let cases: [(&[u8], ParseError); 9] = [
+      (b"Hamburg", ParseError::MissingSemicolon(0)),
+      (b"Hamburg\nOslo;1.0", ParseError::MissingSemicolon(0)),
+      (b"Oslo;1.0\n;2.0", ParseError::InvalidName(9, NameError::Empty)),
+      (b"Hamburg;", ParseError::InvalidTemperature(8)),
+      (b"Hamburg;-", ParseError::InvalidTemperature(8)),
+      (b"Hamburg;1.", ParseError::InvalidTemperature(8)),
+      (b"Hamburg;1x.0", ParseError::InvalidTemperature(8)),
+      (b"Hamburg;1000.0", ParseError::InvalidTemperature(8)),
+      (b"Hamburg;1.00\n", ParseError::InvalidTemperature(8)),
+    ];
+    for (input, expected) in cases {
+      assert_eq!(parse_all(input).err(), Some(expected), "{:?}", String::from_utf8_lossy(input));
+    }
+  }
+
+  #[test]
+  fn reports_a_full_map() {
+    let mut input = Vec::new();
+    for i in 0..MAP_ENTRIES {
+      input.extend_from_slice(format!("{};1.0\n", i).as_bytes());
+    }
+    let full_at = input.len() - format!("{};1.0\n", MAP_ENTRIES - 1).len();
+    assert_eq!(parse_all(&input).err(), Some(ParseError::MapFull(full_at)));
+
+    let long_name = vec![b'a'; MAP_NAME_SIZE / 2 + 1];
+    let mut map = FixedSizeMap::new();
+    assert!(map.get_or_insert(&long_name).is_some());
+    assert!(map.get_or_insert(&long_name[1..]).is_none());
+    assert!(map.get_or_insert(&long_name).is_some());
+  }
+
+  #[test]
+  fn rounds_means_half_away_from_zero() {
+    assert_eq!(format_mean(3, 2), "0.2");
+    assert_eq!(format_mean(-3, 2), "-0.2");
+    assert_eq!(format_mean(1, 3), "0.0");
+    assert_eq!(format_mean(-1, 30), "-0.0");
+    assert_eq!(format_mean(-1005, 1), "-100.5");
+    assert_eq!(format_mean(0, 4), "0.0");
+    assert_eq!(format_mean(i64::MAX, 1), format!("{}.{}", i64::MAX / 10, i64::MAX % 10));
+  }
 }
@@ -0,0 +1,319 @@
+use std::{fs::File, path::Path};
+
+use memchr::memchr;
+use memmap2::MmapOptions;
+
+use crate::{
+  input::Input,
+  names::{checked_name_len, name_str, NameConfig},
+};
+
+#[derive(Copy, Clone, Debug)]
+struct Record {
+  total: i32,
+  min: i32,
+  max: i32,
+  num: usize,
+}
+
+impl Record {
+  fn new() -> Self {
+    Self {
+      total: 0,
+      min: i32::MAX,
+      max: i32::MIN,
+      num: 0,
+    }
+  }
+}
+
+struct RawBufReader<'a> {
+  current_ind: usize,
+  buffer: &'a [u8],
+}
+
+impl<'a> RawBufReader<'a> {
+  fn new(buffer: &'a [u8]) -> Self {
+    Self {
+      buffer,
+      current_ind: 0,
+    }
+  }
+
+  fn has_next_line(&self) -> bool {
+    self.buffer.len() > self.current_ind
+  }
+}
+
+fn parse_temperature(reader: &mut RawBufReader) -> i32 {
+  let (value, len) = parse_temperature_at(reader.buffer, reader.current_ind);
+  reader.current_ind += len;
+  value
+}
+
+/// parse the temperature starting at `ind`, returning the value in tenths of a
+/// degree and the number of bytes consumed (including the trailing newline)
+#[inline]
+fn parse_temperature_at(buffer: &[u8], ind: usize) -> (i32, usize) {
+  match buffer.get(ind..ind + 8) {
+    Some(word) => parse_temperature_swar(u64::from_le_bytes(word.try_into().unwrap())),
+    // less than a word left at the end of the file
+    None => parse_temperature_scalar(&buffer[ind..]),
+  }
+}
+
+/// branchless parsing of `-?\d?\d\.\d\n` packed little endian into a u64
+///
+/// based off https://questdb.io/blog/1brc-merykittys-magic-swar/
+#[inline]
+fn parse_temperature_swar(word: u64) -> (i32, usize) {
+  // digits have bit 4 set, '.' doesn't, so the first cleared bit among bytes
+  // 1..=3 is the decimal point
+  let dot_pos = (!word & 0x10101000).trailing_zeros();
+  // all ones if the first byte is '-' (which also has bit 4 cleared), else 0
+  let signed = (((!word) << 59) as i64 >> 63) as u64;
+  let sign_mask = !(signed & 0xff);
+  // line the digits up so that the decimal point is always byte 3, then keep
+  // the tens (byte 1), ones (byte 2) and tenths (byte 4)
+  let digits = ((word & sign_mask) << (28 - dot_pos)) & 0x0f000f0f00;
+  // 100 * tens + 10 * ones + tenths ends up in bits 32..42
+  let abs_value = (digits.wrapping_mul(0x640a0001) >> 32) & 0x3ff;
+  let value = (abs_value ^ signed).wrapping_sub(signed) as i64 as i32;
+  (value, (dot_pos as usize >> 3) + 3)
+}
+
+/// same as `parse_temperature_swar` but reads byte by byte
+fn parse_temperature_scalar(buffer: &[u8]) -> (i32, usize) {
+  let is_negative = buffer[0] == b'-';
+  let sign_len = is_negative as usize;
+  let digits = &buffer[sign_len..];
+
+  let (a, b, c, increment) = if digits[1] == b'.' {
+    (0, digits[0] - b'0', digits[2] - b'0', 4)
+  } else {
+    (digits[0] - b'0', digits[1] - b'0', digits[3] - b'0', 5)
+  };
+  let temperature_value: i32 = 100 * a as i32 + 10 * b as i32 + c as i32;
+
+  if is_negative {
+    (-temperature_value, increment + sign_len)
+  } else {
+    (temperature_value, increment + sign_len)
+  }
+}
+
+#[derive(Clone, Copy)]
+struct MapStrRef {
+  start: usize,
+  end: usize,
+}
+
+#[derive(Clone, Copy)]
+struct MapKvPair {
+  key: MapStrRef,
+  value: Record,
+}
+
+impl MapKvPair {
+  fn new() -> Self {
+    Self {
+      key: MapStrRef { start: 0, end: 0 },
+      value: Record::new(),
+    }
+  }
+}
+
+const MAP_NAME_SIZE: usize = 1048576;
+const MAP_ENTRIES: usize = 16384;
+
+/// fnv a hash
+fn fnv_hash(value: &[u8]) -> usize {
+  let l = value.len().min(8);
+  let mut conv_key = 0;
+  for (i, b) in value[..l].iter().enumerate() {
+    conv_key |= (*b as u64) << (8 * i);
+  }
+  conv_key ^= value.len() as u64;
+  conv_key.wrapping_mul(16381) as usize
+}
+
+struct FixedSizeMap {
+  names: [u8; MAP_NAME_SIZE],
+  entries: [MapKvPair; MAP_ENTRIES],
+  last_name_idx: usize,
+}
+
+impl FixedSizeMap {
+  fn new() -> Self {
+    Self {
+      names: [0; MAP_NAME_SIZE],
+      entries: [MapKvPair::new(); MAP_ENTRIES],
+      last_name_idx: 0,
+    }
+  }
+
+  fn get_or_insert(&mut self, name: &[u8]) -> usize {
+    let hash = fnv_hash(name);
+    let mut idx = hash % self.entries.len();
+    loop {
+      let cur_entry = unsafe { self.entries.get_unchecked_mut(idx) };
+      if cur_entry.key.start == 0 && cur_entry.key.end == 0 {
+        let name_start = self.last_name_idx;
+        let name_end = self.last_name_idx + name.len();
+        cur_entry.key.start = name_start;
+        cur_entry.key.end = name_end;
+        self.names[name_start..name_end].copy_from_slice(name);
+        self.last_name_idx = name_end;
+        return idx;
+      }
+      let entry = &self.entries[idx];
+
+      // linear probing
+      if &self.names[entry.key.start..entry.key.end] != name {
+        idx = (idx + 1) % self.entries.len();
+        continue;
+      }
+
+      return idx;
+    }
+  }
+
+  fn get(&self, name: &[u8]) -> Record {
+    let hash = fnv_hash(name);
+    let mut idx = hash % self.entries.len();
+    loop {
+      let entry = &self.entries[idx];
+
+      // linear probing
+      if &self.names[entry.key.start..entry.key.end] != name {
+        idx = (idx + 1) % self.entries.len();
+        continue;
+      }
+
+      return entry.value;
+    }
+  }
+
+  fn keys(&self) -> Vec<&[u8]> {
+    self
+      .entries
+      .iter()
+      .filter(|e| e.key.start != e.key.end)
+      .map(|e| &self.names[e.key.start..e.key.end])
+      .collect()
+  }
+}
+
+/// branchless (swar) temperature parsing
+pub fn solve_v11(input: &Path, names: &NameConfig) -> String {
+  let file = File::open(input).unwrap();
+  let mmap = unsafe { MmapOptions::new().map(&file).unwrap() };
+  solve_v11_input(&mmap, names)
+}
+
+/// `solve_v11` on anything already in memory
+pub fn solve_v11_input(input: &(impl Input + ?Sized), names: &NameConfig) -> String {
+  let mut station_values = FixedSizeMap::new();
+  let mut name_buffer = String::new();
+  let mut bufreader = RawBufReader::new(input.bytes());
+
+  println!("Reading");
+
+  while bufreader.has_next_line() {
+    // first character should be non newline + city name
+    let station_start_ind = bufreader.current_ind;
+    let semicolon_ind = memchr(b';', &bufreader.buffer[bufreader.current_ind..]).unwrap();
+    let station_end_ind = semicolon_ind + station_start_ind;
+    bufreader.current_ind += semicolon_ind + 1;
+
+    let value = parse_temperature(&mut bufreader);
+
+    let station_name = &bufreader.buffer[station_start_ind..station_end_ind];
+    let station_name =
+      names.normalize.apply(station_name, &mut name_buffer).map_or(station_name, str::as_bytes);
+    let station_name = &station_name[..checked_name_len(station_name, names.policy).unwrap()];
+    let value_idx = station_values.get_or_insert(station_name);
+    let value_entry = &mut station_values.entries[value_idx].value;
+    value_entry.total += value;
+    value_entry.min = value_entry.min.min(value);
+    value_entry.max = value_entry.max.max(value);
+    value_entry.num += 1;
+  }
+  println!("Summing");
+  let mut station_keys: Vec<_> = station_values.keys();
+  names.order.sort(&mut station_keys);
+  let mut result = String::new();
+  result.push('{');
+  for (i, key) in station_keys.iter().enumerate() {
+    let values = station_values.get(key);
+    let min = values.min as f32 / 10.0;
+    let avg = values.total as f32 / 10.0 / values.num as f32;
+    let max = values.max as f32 / 10.0;
+    if i != 0 {
+      result.push_str(", ");
+    }
+    result.push_str(&format!(
+      "{}={:.1}/{:.1}/{:.1}",
+      name_str(key).unwrap(),
+      min,
+      avg,
+      max
+    ));
+  }
+  result.push_str("}\n");
+  result
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// every temperature between -99.9 and 99.9 along with its text form
+  fn all_temperatures() -> impl Iterator<Item = (i32, String)> {
+    (-999..=999).map(|v: i32| {
+      let sign = if v < 0 { "-" } else { "" };
+      let abs = v.abs();
+      (v, format!("{}{}.{}\n", sign, abs / 10, abs % 10))
+    })
+  }
+
+  #[test]
+  fn swar_parses_every_temperature() {
+    for (expected, line) in all_temperatures() {
+      // pad with the start of a following line like in a real file
+      let mut buffer = line.clone().into_bytes();
+      buffer.extend_from_slice(b"Hamburg;12.0\n");
+      assert_eq!(
+        parse_temperature_at(&buffer, 0),
+        (expected, line.len()),
+        "{:?}",
+        line
+      );
+    }
+  }
+
+  #[test]
+  fn scalar_parses_every_temperature() {
+    for (expected, line) in all_temperatures() {
+      assert_eq!(
+        parse_temperature_scalar(line.as_bytes()),
+        (expected, line.len()),
+        "{:?}",
+        line
+      );
+    }
+  }
+
+  #[test]
+  fn parses_temperatures_at_end_of_buffer() {
+    for (expected, line) in all_temperatures() {
+      let buffer = format!("Hamburg;{}", line).into_bytes();
+      assert_eq!(
+        parse_temperature_at(&buffer, "Hamburg;".len()),
+        (expected, line.len()),
+        "{:?}",
+        line
+      );
+    }
+  }
+}
@@ -0,0 +1,319 @@
+use std::{fs::File, path::Path};
+
+use memmap2::MmapOptions;
+
+use crate::{
+  input::Input,
+  names::{checked_name_len, name_str, NameConfig},
+  scan::{detect_scan_fn, LineSplitter},
+};
+
+#[derive(Copy, Clone, Debug)]
+struct Record {
+  total: i32,
+  min: i32,
+  max: i32,
+  num: usize,
+}
+
+impl Record {
+  fn new() -> Self {
+    Self {
+      total: 0,
+      min: i32::MAX,
+      max: i32::MIN,
+      num: 0,
+    }
+  }
+}
+
+/// parse the temperature starting at `ind`, returning the value in tenths of a
+/// degree and the number of bytes consumed (including the trailing newline)
+#[inline]
+fn parse_temperature_at(buffer: &[u8], ind: usize) -> (i32, usize) {
+  match buffer.get(ind..ind + 8) {
+    Some(word) => parse_temperature_swar(u64::from_le_bytes(word.try_into().unwrap())),
+    // less than a word left at the end of the file
+    None => parse_temperature_scalar(&buffer[ind..]),
+  }
+}
+
+/// branchless parsing of `-?\d?\d\.\d\n` packed little endian into a u64
+///
+/// based off https://questdb.io/blog/1brc-merykittys-magic-swar/
+#[inline]
+fn parse_temperature_swar(word: u64) -> (i32, usize) {
+  // digits have bit 4 set, '.' doesn't, so the first cleared bit among bytes
+  // 1..=3 is the decimal point
+  let dot_pos = (!word & 0x10101000).trailing_zeros();
+  // all ones if the first byte is '-' (which also has bit 4 cleared), else 0
+  let signed = (((!word) << 59) as i64 >> 63) as u64;
+  let sign_mask = !(signed & 0xff);
+  // line the digits up so that the decimal point is always byte 3, then keep
+  // the tens (byte 1), ones (byte 2) and tenths (byte 4)
+  let digits = ((word & sign_mask) << (28 - dot_pos)) & 0x0f000f0f00;
+  // 100 * tens + 10 * ones + tenths ends up in bits 32..42
+  let abs_value = (digits.wrapping_mul(0x640a0001) >> 32) & 0x3ff;
+  let value = (abs_value ^ signed).wrapping_sub(signed) as i64 as i32;
+  (value, (dot_pos as usize >> 3) + 3)
+}
+
+/// same as `parse_temperature_swar` but reads byte by byte
+fn parse_temperature_scalar(buffer: &[u8]) -> (i32, usize) {
+  let is_negative = buffer[0] == b'-';
+  let sign_len = is_negative as usize;
+  let digits = &buffer[sign_len..];
+
+  let (a, b, c, increment) = if digits[1] == b'.' {
+    (0, digits[0] - b'0', digits[2] - b'0', 4)
+  } else {
+    (digits[0] - b'0', digits[1] - b'0', digits[3] - b'0', 5)
+  };
+  let temperature_value: i32 = 100 * a as i32 + 10 * b as i32 + c as i32;
+
+  if is_negative {
+    (-temperature_value, increment + sign_len)
+  } else {
+    (temperature_value, increment + sign_len)
+  }
+}
+
+/// number of name bytes stored inline in each entry
+const INLINE_NAME_LEN: usize = 16;
+const EMPTY_SLOT: usize = usize::MAX;
+
+/// keeps the first bytes of the low word when masked
+const WORD_MASKS: [u64; 9] = [
+  0,
+  0xff,
+  0xffff,
+  0xffffff,
+  0xffffffff,
+  0xffffffffff,
+  0xffffffffffff,
+  0xffffffffffffff,
+  0xffffffffffffffff,
+];
+
+/// first `INLINE_NAME_LEN` bytes of `name` as zero padded little endian words
+#[inline]
+fn key_words(name: &[u8]) -> [u64; 2] {
+  let mut bytes = [0u8; INLINE_NAME_LEN];
+  let l = name.len().min(INLINE_NAME_LEN);
+  bytes[..l].copy_from_slice(&name[..l]);
+  [
+    u64::from_le_bytes(bytes[..8].try_into().unwrap()),
+    u64::from_le_bytes(bytes[8..].try_into().unwrap()),
+  ]
+}
+
+/// same as `key_words(&buffer[start..start + len])`, but loads whole words
+/// and masks off whatever follows the name instead of copying byte by byte
+#[inline]
+fn key_words_at(buffer: &[u8], start: usize, len: usize) -> [u64; 2] {
+  match buffer.get(start..start + INLINE_NAME_LEN) {
+    Some(bytes) => {
+      let lo = u64::from_le_bytes(bytes[..8].try_into().unwrap());
+      let hi = u64::from_le_bytes(bytes[8..].try_into().unwrap());
+      [
+        lo & WORD_MASKS[len.min(8)],
+        hi & WORD_MASKS[len.clamp(8, 16) - 8],
+      ]
+    }
+    None => key_words(&buffer[start..start + len]),
+  }
+}
+
+#[derive(Clone, Copy)]
+struct MapKey {
+  /// first `INLINE_NAME_LEN` bytes of the name
+  words: [u64; 2],
+  len: usize,
+  /// where the full name lives in `names`, only used for long names
+  start: usize,
+}
+
+#[derive(Clone, Copy)]
+struct MapKvPair {
+  key: MapKey,
+  value: Record,
+}
+
+impl MapKvPair {
+  fn new() -> Self {
+    Self {
+      key: MapKey {
+        words: [0, 0],
+        len: EMPTY_SLOT,
+        start: 0,
+      },
+      value: Record::new(),
+    }
+  }
+}
+
+const MAP_NAME_SIZE: usize = 1048576;
+const MAP_ENTRIES: usize = 16384;
+
+/// hash over the first word of the name. The product is folded so the low
+/// bits the map indexes with depend on every byte of the word, not just the
+/// first two
+#[inline]
+fn fnv_hash(first_word: u64, len: usize) -> usize {
+  let product = (first_word ^ len as u64) as u128 * 0x9e37_79b9_7f4a_7c15;
+  (product as u64 ^ (product >> 64) as u64) as usize
+}
+
+struct FixedSizeMap {
+  names: [u8; MAP_NAME_SIZE],
+  entries: [MapKvPair; MAP_ENTRIES],
+  last_name_idx: usize,
+}
+
+impl FixedSizeMap {
+  fn new() -> Self {
+    Self {
+      names: [0; MAP_NAME_SIZE],
+      entries: [MapKvPair::new(); MAP_ENTRIES],
+      last_name_idx: 0,
+    }
+  }
+
+  #[inline]
+  fn matches(&self, key: &MapKey, name: &[u8], words: [u64; 2]) -> bool {
+    if key.words != words || key.len != name.len() {
+      return false;
+    }
+    name.len() <= INLINE_NAME_LEN
+      || self.names[key.start + INLINE_NAME_LEN..key.start + key.len] == name[INLINE_NAME_LEN..]
+  }
+
+  /// `words` has to be `key_words(name)`
+  fn get_or_insert(&mut self, name: &[u8], words: [u64; 2]) -> usize {
+    let hash = fnv_hash(words[0], name.len());
+    let mut idx = hash % self.entries.len();
+    loop {
+      let cur_entry = unsafe { self.entries.get_unchecked_mut(idx) };
+      if cur_entry.key.len == EMPTY_SLOT {
+        cur_entry.key.words = words;
+        cur_entry.key.len = name.len();
+        // short names never touch the arena
+        if name.len() > INLINE_NAME_LEN {
+          let name_start = self.last_name_idx;
+          let name_end = self.last_name_idx + name.len();
+          cur_entry.key.start = name_start;
+          self.names[name_start..name_end].copy_from_slice(name);
+          self.last_name_idx = name_end;
+        }
+        return idx;
+      }
+
+      // linear probing
+      if !self.matches(&self.entries[idx].key, name, words) {
+        idx = (idx + 1) % self.entries.len();
+        continue;
+      }
+
+      return idx;
+    }
+  }
+
+  fn get(&self, name: &[u8]) -> Record {
+    let words = key_words(name);
+    let hash = fnv_hash(words[0], name.len());
+    let mut idx = hash % self.entries.len();
+    loop {
+      let entry = &self.entries[idx];
+
+      // linear probing
+      if !self.matches(&entry.key, name, words) {
+        idx = (idx + 1) % self.entries.len();
+        continue;
+      }
+
+      return entry.value;
+    }
+  }
+
+  fn key_name(&self, key: &MapKey) -> Vec<u8> {
+    if key.len <= INLINE_NAME_LEN {
+      let mut name = key.words[0].to_le_bytes().to_vec();
+      name.extend_from_slice(&key.words[1].to_le_bytes());
+      name.truncate(key.len);
+      name
+    } else {
+      self.names[key.start..key.start + key.len].to_vec()
+    }
+  }
+
+  fn keys(&self) -> Vec<Vec<u8>> {
+    self
+      .entries
+      .iter()
+      .filter(|e| e.key.len != EMPTY_SLOT)
+      .map(|e| self.key_name(&e.key))
+      .collect()
+  }
+}
+
+/// keep names up to 16 bytes inline in the map entries and compare them as two
+/// words instead of going through the name arena
+pub fn solve_v14(input: &Path, names: &NameConfig) -> String {
+  let file = File::open(input).unwrap();
+  let mmap = unsafe { MmapOptions::new().map(&file).unwrap() };
+  solve_v14_input(&mmap, names)
+}
+
+/// `solve_v14` on anything already in memory
+pub fn solve_v14_input(input: &(impl Input + ?Sized), names: &NameConfig) -> String {
+  let mut station_values = FixedSizeMap::new();
+  let mut name_buffer = String::new();
+
+  println!("Reading");
+
+  let buffer = input.bytes();
+  let mut station_start_ind = 0;
+  for (semicolon_ind, newline_ind) in LineSplitter::new(buffer, detect_scan_fn()) {
+    let (value, _) = parse_temperature_at(buffer, semicolon_ind + 1);
+
+    let station_name = &buffer[station_start_ind..semicolon_ind];
+    let normalized = names.normalize.apply(station_name, &mut name_buffer).map(str::as_bytes);
+    let station_name = normalized.unwrap_or(station_name);
+    let station_name = &station_name[..checked_name_len(station_name, names.policy).unwrap()];
+    let words = match normalized {
+      // the rest of the file follows the name, so its words can be read in place
+      None => key_words_at(buffer, station_start_ind, station_name.len()),
+      Some(_) => key_words(station_name),
+    };
+    let value_idx = station_values.get_or_insert(station_name, words);
+    let value_entry = &mut station_values.entries[value_idx].value;
+    value_entry.total += value;
+    value_entry.min = value_entry.min.min(value);
+    value_entry.max = value_entry.max.max(value);
+    value_entry.num += 1;
+    station_start_ind = newline_ind + 1;
+  }
+  println!("Summing");
+  let mut station_keys: Vec<_> = station_values.keys();
+  names.order.sort(&mut station_keys);
+  let mut result = String::new();
+  result.push('{');
+  for (i, key) in station_keys.iter().enumerate() {
+    let values = station_values.get(key);
+    let min = values.min as f32 / 10.0;
+    let avg = values.total as f32 / 10.0 / values.num as f32;
+    let max = values.max as f32 / 10.0;
+    if i != 0 {
+      result.push_str(", ");
+    }
+    result.push_str(&format!(
+      "{}={:.1}/{:.1}/{:.1}",
+      name_str(key).unwrap(),
+      min,
+      avg,
+      max
+    ));
+  }
+  result.push_str("}\n");
+  result
+}
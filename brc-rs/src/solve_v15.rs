@@ -0,0 +1,687 @@
+use std::{
+  fs::File,
+  io,
+  path::Path,
+  sync::atomic::{AtomicBool, Ordering},
+  thread,
+};
+
+use memchr::memchr;
+use memmap2::Mmap;
+
+use crate::{
+  affinity::ThreadPlan,
+  format::InputFormat,
+  input::Input,
+  mmap::{map_file, prefault, MmapConfig},
+  names::{checked_name_len, name_str, NameConfig},
+  reader::{window_size, ReaderConfig, ReaderKind},
+  scan::{detect_scan_fn, LineSplitter, ScanFn},
+  scheduler::{ChunkScheduler, DEFAULT_CHUNK_SIZE},
+  solve_v10::{format_mean, ParseError},
+  teardown,
+};
+
+#[cfg(unix)]
+use crate::reader::read_windows;
+#[cfg(target_os = "linux")]
+use crate::uring_reader::read_uring;
+
+#[derive(Copy, Clone, Debug)]
+struct Record {
+  total: i64,
+  min: i32,
+  max: i32,
+  /// rows with a missing value, which aren't in the others
+  missing: usize,
+  num: usize,
+}
+
+impl Record {
+  fn new() -> Self {
+    Self {
+      total: 0,
+      min: i32::MAX,
+      max: i32::MIN,
+      missing: 0,
+      num: 0,
+    }
+  }
+
+  /// whether no row has been counted yet
+  #[inline]
+  fn is_new(&self) -> bool {
+    self.num == 0 && self.missing == 0
+  }
+
+  #[inline]
+  fn add(&mut self, value: i32) {
+    self.total += value as i64;
+    self.min = self.min.min(value);
+    self.max = self.max.max(value);
+    self.num += 1;
+  }
+
+  fn merge(&mut self, other: &Record) {
+    self.total += other.total;
+    self.min = self.min.min(other.min);
+    self.max = self.max.max(other.max);
+    self.missing += other.missing;
+    self.num += other.num;
+  }
+}
+
+struct RawBufReader {
+  mmap: Mmap,
+}
+
+impl RawBufReader {
+  fn new(file: File, config: &MmapConfig) -> Self {
+    Self {
+      mmap: map_file(&file, config).unwrap(),
+    }
+  }
+}
+
+/// parse the temperature starting at `ind`, returning the value in tenths of a
+/// degree and the number of bytes consumed (including the trailing newline)
+#[inline]
+pub fn parse_temperature_at(buffer: &[u8], ind: usize) -> (i32, usize) {
+  match buffer.get(ind..ind + 8) {
+    Some(word) => parse_temperature_swar(u64::from_le_bytes(word.try_into().unwrap())),
+    // less than a word left at the end of the file
+    None => parse_temperature_scalar(&buffer[ind..]),
+  }
+}
+
+/// branchless parsing of `-?\d?\d\.\d\n` packed little endian into a u64
+///
+/// based off https://questdb.io/blog/1brc-merykittys-magic-swar/
+#[inline]
+fn parse_temperature_swar(word: u64) -> (i32, usize) {
+  // digits have bit 4 set, '.' doesn't, so the first cleared bit among bytes
+  // 1..=3 is the decimal point
+  let dot_pos = (!word & 0x10101000).trailing_zeros();
+  // all ones if the first byte is '-' (which also has bit 4 cleared), else 0
+  let signed = (((!word) << 59) as i64 >> 63) as u64;
+  let sign_mask = !(signed & 0xff);
+  // line the digits up so that the decimal point is always byte 3, then keep
+  // the tens (byte 1), ones (byte 2) and tenths (byte 4)
+  let digits = ((word & sign_mask) << (28 - dot_pos)) & 0x0f000f0f00;
+  // 100 * tens + 10 * ones + tenths ends up in bits 32..42
+  let abs_value = (digits.wrapping_mul(0x640a0001) >> 32) & 0x3ff;
+  let value = (abs_value ^ signed).wrapping_sub(signed) as i64 as i32;
+  (value, (dot_pos as usize >> 3) + 3)
+}
+
+/// same as `parse_temperature_swar` but reads byte by byte
+fn parse_temperature_scalar(buffer: &[u8]) -> (i32, usize) {
+  let is_negative = buffer[0] == b'-';
+  let sign_len = is_negative as usize;
+  let digits = &buffer[sign_len..];
+
+  let (a, b, c, increment) = if digits[1] == b'.' {
+    (0, digits[0] - b'0', digits[2] - b'0', 4)
+  } else {
+    (digits[0] - b'0', digits[1] - b'0', digits[3] - b'0', 5)
+  };
+  let temperature_value: i32 = 100 * a as i32 + 10 * b as i32 + c as i32;
+
+  if is_negative {
+    (-temperature_value, increment + sign_len)
+  } else {
+    (temperature_value, increment + sign_len)
+  }
+}
+
+/// number of name bytes stored inline in each entry
+const INLINE_NAME_LEN: usize = 16;
+const EMPTY_SLOT: usize = usize::MAX;
+
+/// keeps the first bytes of the low word when masked
+const WORD_MASKS: [u64; 9] = [
+  0,
+  0xff,
+  0xffff,
+  0xffffff,
+  0xffffffff,
+  0xffffffffff,
+  0xffffffffffff,
+  0xffffffffffffff,
+  0xffffffffffffffff,
+];
+
+/// first `INLINE_NAME_LEN` bytes of `name` as zero padded little endian words
+#[inline]
+pub fn key_words(name: &[u8]) -> [u64; 2] {
+  let mut bytes = [0u8; INLINE_NAME_LEN];
+  let l = name.len().min(INLINE_NAME_LEN);
+  bytes[..l].copy_from_slice(&name[..l]);
+  [
+    u64::from_le_bytes(bytes[..8].try_into().unwrap()),
+    u64::from_le_bytes(bytes[8..].try_into().unwrap()),
+  ]
+}
+
+/// same as `key_words(&buffer[start..start + len])`, but loads whole words
+/// and masks off whatever follows the name instead of copying byte by byte
+#[inline]
+fn key_words_at(buffer: &[u8], start: usize, len: usize) -> [u64; 2] {
+  match buffer.get(start..start + INLINE_NAME_LEN) {
+    Some(bytes) => {
+      let lo = u64::from_le_bytes(bytes[..8].try_into().unwrap());
+      let hi = u64::from_le_bytes(bytes[8..].try_into().unwrap());
+      [
+        lo & WORD_MASKS[len.min(8)],
+        hi & WORD_MASKS[len.clamp(8, 16) - 8],
+      ]
+    }
+    None => key_words(&buffer[start..start + len]),
+  }
+}
+
+const HIGH_BITS: u64 = 0x8080_8080_8080_8080;
+
+/// whether the name in `words` is ascii
+#[inline]
+fn is_ascii_words(words: [u64; 2]) -> bool {
+  (words[0] | words[1]) & HIGH_BITS == 0
+}
+
+/// `words` of an ascii name with `A` to `Z` lowercased. No byte is above
+/// 0x7f, so the additions never carry into the next byte.
+#[inline]
+fn ascii_lowercase_words(words: [u64; 2]) -> [u64; 2] {
+  words.map(|word| {
+    // high bit set in bytes from b'A' and in bytes past b'Z'
+    let from_a = word + 0x3f3f_3f3f_3f3f_3f3f;
+    let past_z = word + 0x2525_2525_2525_2525;
+    word | ((from_a & !past_z & HIGH_BITS) >> 2)
+  })
+}
+
+#[derive(Clone, Copy)]
+struct MapKey {
+  /// first `INLINE_NAME_LEN` bytes of the name
+  words: [u64; 2],
+  len: usize,
+  /// where the full name lives in `names`, only used for long names
+  start: usize,
+}
+
+#[derive(Clone, Copy)]
+struct MapKvPair {
+  key: MapKey,
+  value: Record,
+}
+
+impl MapKvPair {
+  fn new() -> Self {
+    Self {
+      key: MapKey {
+        words: [0, 0],
+        len: EMPTY_SLOT,
+        start: 0,
+      },
+      value: Record::new(),
+    }
+  }
+}
+
+const MAP_NAME_SIZE: usize = 1048576;
+const MAP_ENTRIES: usize = 16384;
+
+/// hash over the first word of the name. The product is folded so the low
+/// bits the map indexes with depend on every byte of the word, not just the
+/// first two
+#[inline]
+pub fn fnv_hash(first_word: u64, len: usize) -> usize {
+  let product = (first_word ^ len as u64) as u128 * 0x9e37_79b9_7f4a_7c15;
+  (product as u64 ^ (product >> 64) as u64) as usize
+}
+
+/// heap allocated since every thread gets its own, which wouldn't fit on a
+/// thread's stack
+pub struct FixedSizeMap {
+  names: Vec<u8>,
+  entries: Vec<MapKvPair>,
+  last_name_idx: usize,
+}
+
+impl FixedSizeMap {
+  /// memory used by each map
+  const HEAP_SIZE: usize = MAP_NAME_SIZE + MAP_ENTRIES * std::mem::size_of::<MapKvPair>();
+
+  pub fn new() -> Self {
+    Self {
+      names: vec![0; MAP_NAME_SIZE],
+      entries: vec![MapKvPair::new(); MAP_ENTRIES],
+      last_name_idx: 0,
+    }
+  }
+
+  #[inline]
+  fn matches(&self, key: &MapKey, name: &[u8], words: [u64; 2]) -> bool {
+    if key.words != words || key.len != name.len() {
+      return false;
+    }
+    name.len() <= INLINE_NAME_LEN
+      || self.names[key.start + INLINE_NAME_LEN..key.start + key.len] == name[INLINE_NAME_LEN..]
+  }
+
+  /// `words` has to be `key_words(name)`. Names of up to `INLINE_NAME_LEN`
+  /// bytes are only told apart by their words and length.
+  pub fn get_or_insert(&mut self, name: &[u8], words: [u64; 2]) -> usize {
+    let hash = fnv_hash(words[0], name.len());
+    let mut idx = hash % self.entries.len();
+    loop {
+      let cur_entry = unsafe { self.entries.get_unchecked_mut(idx) };
+      if cur_entry.key.len == EMPTY_SLOT {
+        cur_entry.key.words = words;
+        cur_entry.key.len = name.len();
+        // short names never touch the arena
+        if name.len() > INLINE_NAME_LEN {
+          let name_start = self.last_name_idx;
+          let name_end = self.last_name_idx + name.len();
+          cur_entry.key.start = name_start;
+          self.names[name_start..name_end].copy_from_slice(name);
+          self.last_name_idx = name_end;
+        }
+        return idx;
+      }
+
+      // linear probing
+      if !self.matches(&self.entries[idx].key, name, words) {
+        idx = (idx + 1) % self.entries.len();
+        continue;
+      }
+
+      return idx;
+    }
+  }
+
+  fn get(&self, name: &[u8]) -> Record {
+    let words = key_words(name);
+    let hash = fnv_hash(words[0], name.len());
+    let mut idx = hash % self.entries.len();
+    loop {
+      let entry = &self.entries[idx];
+
+      // linear probing
+      if !self.matches(&entry.key, name, words) {
+        idx = (idx + 1) % self.entries.len();
+        continue;
+      }
+
+      return entry.value;
+    }
+  }
+
+  fn key_name(&self, key: &MapKey) -> Vec<u8> {
+    if key.len <= INLINE_NAME_LEN {
+      let mut name = key.words[0].to_le_bytes().to_vec();
+      name.extend_from_slice(&key.words[1].to_le_bytes());
+      name.truncate(key.len);
+      name
+    } else {
+      self.names[key.start..key.start + key.len].to_vec()
+    }
+  }
+
+  fn merge(&mut self, other: &FixedSizeMap) {
+    for entry in other.entries.iter().filter(|e| e.key.len != EMPTY_SLOT) {
+      let name = other.key_name(&entry.key);
+      let value_idx = self.get_or_insert(&name, entry.key.words);
+      self.entries[value_idx].value.merge(&entry.value);
+    }
+  }
+
+  fn keys(&self) -> Vec<Vec<u8>> {
+    self
+      .entries
+      .iter()
+      .filter(|e| e.key.len != EMPTY_SLOT)
+      .map(|e| self.key_name(&e.key))
+      .collect()
+  }
+}
+
+impl Default for FixedSizeMap {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+/// Adds the lines of `chunk`, which starts at byte `offset` of the input, to
+/// `station_values`. Errors point at the input like v10's `parse`.
+fn process_chunk(
+  chunk: &[u8],
+  offset: usize,
+  scan: ScanFn,
+  names: &NameConfig,
+  format: &InputFormat,
+  station_values: &mut FixedSizeMap,
+) -> Result<(), ParseError> {
+  if !format.lines_are_plain() {
+    return process_lines(chunk, offset, names, format, station_values);
+  }
+  // separate loops, so that normalizing costs nothing per line when it's off
+  if names.normalize.is_enabled() {
+    process_plain_lines::<true>(chunk, offset, scan, names, station_values)
+  } else {
+    process_plain_lines::<false>(chunk, offset, scan, names, station_values)
+  }
+}
+
+/// `process_chunk` for plain `name;temperature` lines. Short ascii names are
+/// already normalized and case folding only lowercases their words, longer
+/// and non ascii ones go through `Normalize::apply`.
+#[inline(always)]
+fn process_plain_lines<const NORMALIZE: bool>(
+  chunk: &[u8],
+  offset: usize,
+  scan: ScanFn,
+  names: &NameConfig,
+  station_values: &mut FixedSizeMap,
+) -> Result<(), ParseError> {
+  let mut name_buffer = String::new();
+  let mut station_start_ind = 0;
+  for (semicolon_ind, newline_ind) in LineSplitter::new(chunk, scan) {
+    let (value, _) = parse_temperature_at(chunk, semicolon_ind + 1);
+
+    let raw_name = &chunk[station_start_ind..semicolon_ind];
+    // the rest of the chunk follows the name, so its words can be read in place
+    let mut words = key_words_at(chunk, station_start_ind, raw_name.len());
+    let normalized = match NORMALIZE {
+      true if raw_name.len() <= INLINE_NAME_LEN && is_ascii_words(words) => {
+        if names.normalize.case_fold {
+          words = ascii_lowercase_words(words);
+        }
+        None
+      }
+      true => names.normalize.apply(raw_name, &mut name_buffer).map(str::as_bytes),
+      false => None,
+    };
+    let station_name = normalized.unwrap_or(raw_name);
+    let name_len = checked_name_len(station_name, names.policy)
+      .map_err(|e| ParseError::InvalidName(offset + station_start_ind, e))?;
+    let station_name = &station_name[..name_len];
+    if normalized.is_some() || name_len != raw_name.len() {
+      words = key_words(station_name);
+    }
+    let value_idx = station_values.get_or_insert(station_name, words);
+    let record = &mut station_values.entries[value_idx].value;
+    // names are only checked to be utf-8 on their station's first row
+    if record.is_new() {
+      name_str(station_name).map_err(|e| ParseError::InvalidName(offset + station_start_ind, e))?;
+    }
+    record.add(value);
+    station_start_ind = newline_ind + 1;
+  }
+  Ok(())
+}
+
+/// `process_chunk` one line at a time, for trimming, other number formats,
+/// missing values and for comments and other lines without exactly one `;`.
+/// Kept apart so that checking for them costs nothing per line when they're
+/// off.
+#[inline(never)]
+fn process_lines(
+  chunk: &[u8],
+  offset: usize,
+  names: &NameConfig,
+  format: &InputFormat,
+  station_values: &mut FixedSizeMap,
+) -> Result<(), ParseError> {
+  let mut name_buffer = String::new();
+  let mut line_start = 0;
+  while line_start < chunk.len() {
+    let line_end = memchr(b'\n', &chunk[line_start..]).map_or(chunk.len(), |ind| line_start + ind);
+    let line = &chunk[line_start..line_end];
+    let station_start_ind = line_start;
+    line_start = line_end + 1;
+    if format.is_comment(line) {
+      continue;
+    }
+    let semicolon_ind = match memchr(b';', line) {
+      Some(semicolon_ind) => station_start_ind + semicolon_ind,
+      None => return Err(ParseError::MissingSemicolon(offset + station_start_ind)),
+    };
+    let value_field = format.field(chunk, semicolon_ind + 1..line_end);
+    // checked unlike `parse_temperature_at`, input that needs these options
+    // isn't trusted to be well formed
+    let value = if format.is_missing(&chunk[value_field.clone()]) {
+      None
+    } else {
+      let value = format.number.parse_tenths(&chunk[value_field.clone()]);
+      Some(value.ok_or(ParseError::InvalidTemperature(offset + value_field.start))?)
+    };
+
+    let name = format.field(chunk, station_start_ind..semicolon_ind);
+    let station_name = &chunk[name.clone()];
+    let normalized = names.normalize.apply(station_name, &mut name_buffer).map(str::as_bytes);
+    let station_name = normalized.unwrap_or(station_name);
+    let name_len = checked_name_len(station_name, names.policy)
+      .map_err(|e| ParseError::InvalidName(offset + station_start_ind, e))?;
+    let station_name = &station_name[..name_len];
+    let words = match normalized {
+      None => key_words_at(chunk, name.start, station_name.len()),
+      Some(_) => key_words(station_name),
+    };
+    let value_idx = station_values.get_or_insert(station_name, words);
+    let record = &mut station_values.entries[value_idx].value;
+    if record.is_new() {
+      name_str(station_name).map_err(|e| ParseError::InvalidName(offset + station_start_ind, e))?;
+    }
+    match value.filter(|value| !format.is_sentinel(*value)) {
+      Some(value) => record.add(value),
+      None => record.missing += 1,
+    }
+  }
+  Ok(())
+}
+
+fn read_input(
+  input: &(impl Input + ?Sized),
+  plan: &ThreadPlan,
+  config: &MmapConfig,
+  scan: ScanFn,
+  names: &NameConfig,
+  format: &InputFormat,
+) -> Result<Vec<FixedSizeMap>, ParseError> {
+  let mmap = input.bytes();
+  let data_start = format.data_start(mmap);
+  let mmap = &mmap[data_start..];
+  let scheduler = ChunkScheduler::new(DEFAULT_CHUNK_SIZE);
+  let done = AtomicBool::new(false);
+  thread::scope(|s| {
+    if config.prefault {
+      s.spawn(|| prefault(mmap, &scheduler, &done));
+    }
+    let threads: Vec<_> = (0..plan.threads)
+      .map(|worker| {
+        let scheduler = &scheduler;
+        s.spawn(move || {
+          plan.pin_worker(worker).unwrap();
+          let mut station_values = FixedSizeMap::new();
+          while let Some(chunk) = scheduler.next_chunk(mmap) {
+            let offset = data_start + chunk.start;
+            process_chunk(&mmap[chunk], offset, scan, names, format, &mut station_values)?;
+          }
+          Ok(station_values)
+        })
+      })
+      .collect();
+    let thread_values = threads.into_iter().map(|t| t.join().unwrap()).collect();
+    done.store(true, Ordering::Relaxed);
+    thread_values
+  })
+}
+
+fn invalid_data(e: ParseError) -> io::Error {
+  io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+/// cut the file into many small chunks handed out through an atomic cursor
+/// (work stealing) and merge the per thread maps at the end
+pub fn solve_v15(
+  input: &Path,
+  plan: &ThreadPlan,
+  reader: &ReaderConfig,
+  mmap_config: &MmapConfig,
+  names: &NameConfig,
+  format: &InputFormat,
+) -> String {
+  let file = File::open(input).unwrap();
+
+  println!("Reading");
+
+  let scan = detect_scan_fn();
+  // with --fork-teardown this is only unmapped when the child exits, after
+  // the parent is gone
+  let mut bufreader = None;
+  let thread_values = match reader.kind {
+    ReaderKind::Mmap => {
+      let bufreader = bufreader.insert(RawBufReader::new(file, mmap_config));
+      read_input(&bufreader.mmap, plan, mmap_config, scan, names, format).map_err(invalid_data)
+    }
+    #[cfg(unix)]
+    ReaderKind::Read => {
+      let window =
+        window_size(reader.memory_budget, plan.threads, FixedSizeMap::HEAP_SIZE).unwrap();
+      let start = format.file_data_start(&file).unwrap();
+      read_windows(&file, start, plan, window, FixedSizeMap::new, |chunk, offset, station_values| {
+        process_chunk(chunk, offset, scan, names, format, station_values).map_err(invalid_data)
+      })
+    }
+    #[cfg(not(unix))]
+    ReaderKind::Read => panic!("the read reader is only available on unix"),
+    #[cfg(target_os = "linux")]
+    ReaderKind::IoUring => {
+      let start = format.file_data_start(&file).unwrap();
+      read_uring(&file, start, plan, FixedSizeMap::new, |chunk, offset, station_values| {
+        process_chunk(chunk, offset, scan, names, format, station_values).map_err(invalid_data)
+      })
+    }
+    #[cfg(not(target_os = "linux"))]
+    ReaderKind::IoUring => panic!("the io-uring reader is only available on linux"),
+  }
+  .unwrap_or_else(|e| panic!("reading {}: {}", input.display(), e));
+
+  let result = summarize(&thread_values, names, format);
+  teardown::leak_if_forked(bufreader);
+  result
+}
+
+/// `solve_v15` with the mmap reader on anything already in memory
+pub fn solve_v15_input(
+  input: &(impl Input + ?Sized),
+  plan: &ThreadPlan,
+  names: &NameConfig,
+  format: &InputFormat,
+) -> String {
+  println!("Reading");
+  let config = MmapConfig::default();
+  let thread_values = read_input(input, plan, &config, detect_scan_fn(), names, format).unwrap();
+  summarize(&thread_values, names, format)
+}
+
+/// merges the per thread maps and formats the result
+fn summarize(thread_values: &[FixedSizeMap], names: &NameConfig, format: &InputFormat) -> String {
+  let mut station_values = FixedSizeMap::new();
+  for partial_values in thread_values {
+    station_values.merge(partial_values);
+  }
+  println!("Summing");
+  let mut station_keys: Vec<_> = station_values.keys();
+  names.order.sort(&mut station_keys);
+  let mut result = String::new();
+  result.push('{');
+  for (i, key) in station_keys.iter().enumerate() {
+    let values = station_values.get(key);
+    if i != 0 {
+      result.push_str(", ");
+    }
+    // checked on the station's first row
+    result.push_str(name_str(key).unwrap());
+    if values.num == 0 {
+      result.push_str("=NA/NA/NA");
+    } else {
+      // temperatures with thousands separators have more digits than an f32
+      let min = values.min as f64 / 10.0;
+      let max = values.max as f64 / 10.0;
+      let mean = format_mean(values.total, values.num);
+      result.push_str(&format!("={:.1}/{}/{:.1}", min, mean, max));
+    }
+    if format.has_missing_values() {
+      result.push_str(&format!("/{}", values.missing));
+    }
+  }
+  result.push_str("}\n");
+  result
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    format::{DecimalSeparator, NumberFormat},
+    names::NameError,
+    solve_v10,
+  };
+
+  fn process_all(input: &[u8], format: &InputFormat) -> Result<(), ParseError> {
+    let start = format.data_start(input);
+    let (names, mut station_values) = (NameConfig::default(), FixedSizeMap::new());
+    process_chunk(&input[start..], start, detect_scan_fn(), &names, format, &mut station_values)
+  }
+
+  #[test]
+  fn lowercases_ascii_words() {
+    let ascii: Vec<u8> = (0..0x80).collect();
+    for name in ascii.chunks(INLINE_NAME_LEN) {
+      let words = key_words(name);
+      assert!(is_ascii_words(words));
+      assert_eq!(ascii_lowercase_words(words), key_words(&name.to_ascii_lowercase()));
+    }
+    assert!(!is_ascii_words(key_words("Zürich".as_bytes())));
+  }
+
+  #[test]
+  fn reports_malformed_lines_like_v10() {
+    let trim = InputFormat {
+      trim: true,
+      ..Default::default()
+    };
+    let comma = InputFormat {
+      number: NumberFormat::new(DecimalSeparator::Comma, None).unwrap(),
+      header_lines: 1,
+      ..Default::default()
+    };
+    let comments = InputFormat {
+      comment_prefix: Some("#".to_string()),
+      ..Default::default()
+    };
+    let plain = InputFormat::default();
+    let invalid_utf8 = |at| ParseError::InvalidName(at, NameError::InvalidUtf8);
+    let cases: [(&[u8], &InputFormat, ParseError); 10] = [
+      (b"Hamburg;12.0\n\nOslo;1.0\n", &trim, ParseError::MissingSemicolon(13)),
+      (b"Hamburg;12.0\nOslo 1.0", &trim, ParseError::MissingSemicolon(13)),
+      (b"Hamburg;12.0\n ;1.0\n", &trim, ParseError::InvalidName(13, NameError::Empty)),
+      (b"station\nHamburg;12,0\nOslo;1.0\n", &comma, ParseError::InvalidTemperature(26)),
+      (b"station\nHamburg;12,0\nOslo;\n", &comma, ParseError::InvalidTemperature(26)),
+      (b"Hamburg;12.0\nOslo;abc\n", &trim, ParseError::InvalidTemperature(18)),
+      (b"# Oslo;abc\nOslo;abc\n", &comments, ParseError::InvalidTemperature(16)),
+      (b"Oslo;1.0\n# Oslo\nOslo;1.23\n", &comments, ParseError::InvalidTemperature(21)),
+      (b"Hamburg;12.0\nZ\xfcrich;1.0\n", &plain, invalid_utf8(13)),
+      (b"Oslo;1.0\n Z\xfcrich ;1.0\n", &trim, invalid_utf8(9)),
+    ];
+    for (input, format, error) in cases {
+      assert_eq!(process_all(input, format), Err(error), "{:?}", input);
+      let mut station_values = solve_v10::FixedSizeMap::new();
+      let v10 = solve_v10::parse(input, &NameConfig::default(), format, &mut station_values);
+      assert_eq!(v10, Err(error), "{:?}", input);
+    }
+    assert_eq!(process_all(b"Hamburg;12.0\nOslo;1.0\n", &trim), Ok(()));
+  }
+}
@@ -1,15 +1,15 @@
 use memmap2::MmapOptions;
-use std::{collections::HashMap, fs::File, io::Read, sync::Arc, thread};
+use std::{collections::HashMap, fs::File, path::Path, sync::Arc, thread};
 
 use ordered_float::NotNan;
 
-use crate::{write_string_to_output, MEASUREMENTS};
+use crate::names::{checked_name_len, NameConfig};
 
 /// Use mmap instead of reading file directly
-pub fn solve_v3() {
-  let mut station_values: HashMap<String, Vec<NotNan<f32>>> = HashMap::new();
+pub fn solve_v3(input: &Path, names: &NameConfig) -> String {
+  let (normalize, name_policy) = (names.normalize, names.policy);
   println!("Reading...");
-  let mut file = File::open(MEASUREMENTS).unwrap();
+  let file = File::open(input).unwrap();
   let buffer = unsafe {
     MmapOptions::new()
       .map(&file)
@@ -39,19 +39,23 @@ pub fn solve_v3() {
     let end_ind = segment_splits[i + 1];
     println!(
       "start segment: {}",
-      std::str::from_utf8(&thread_buffer_ptr[start_ind..start_ind + 10]).unwrap()
+      String::from_utf8_lossy(&thread_buffer_ptr[start_ind..(start_ind + 10).min(end_ind)])
     );
     threads.push(thread::spawn(move || {
       let mut counts: HashMap<String, Vec<NotNan<f32>>> = HashMap::new();
       let file_chunk = std::str::from_utf8(&thread_buffer_ptr[start_ind..end_ind]).unwrap();
+      let mut name_buffer = String::new();
       for line in file_chunk.lines() {
         let pieces: Vec<_> = line.split(";").collect();
         if pieces.len() != 2 {
           println!("weird parsing: {}", line);
         }
+        let name = pieces[0];
+        let name = normalize.apply(name.as_bytes(), &mut name_buffer).unwrap_or(name);
+        let name = &name[..checked_name_len(name.as_bytes(), name_policy).unwrap()];
         counts
-          .entry(pieces[0].to_string())
-          .or_insert(Vec::new())
+          .entry(name.to_string())
+          .or_default()
           .push(pieces[1].parse().unwrap());
       }
       counts
@@ -73,9 +77,9 @@ pub fn solve_v3() {
 
   println!("Summing");
   let mut station_keys: Vec<_> = station_values.keys().collect();
-  station_keys.sort();
+  names.order.sort(&mut station_keys);
   let mut result = String::new();
-  result.push_str("{");
+  result.push('{');
   for (i, key) in station_keys.into_iter().enumerate() {
     let values = station_values.get(key).unwrap();
     let min = values.iter().min().unwrap();
@@ -87,5 +91,5 @@ pub fn solve_v3() {
     result.push_str(&format!("{}={:.1}/{:.1}/{:.1}", key, min, avg, max));
   }
   result.push_str("}\n");
-  write_string_to_output(&result);
+  result
 }
@@ -1,8 +1,7 @@
-use std::{collections::HashMap, fs::{self, File}, io::{BufReader, BufRead}};
+use std::{collections::HashMap, fs::File, io::{BufReader, BufRead}, path::Path};
 
-use ordered_float::NotNan;
+use crate::names::{checked_name_len, NameConfig};
 
-use crate::{write_string_to_output, MEASUREMENTS};
 
 struct Record {
   total: f32,
@@ -12,9 +11,10 @@ struct Record {
 }
 
 /// Simplest solution but optimizes for memory
-pub fn solve_v4()  {
+pub fn solve_v4(input: &Path, names: &NameConfig) -> String {
   let mut station_values: HashMap<String, Record> = HashMap::new();
-  let file = File::open(MEASUREMENTS).unwrap();
+  let mut name_buffer = String::new();
+  let file = File::open(input).unwrap();
   let bufreader = BufReader::new(file);
   println!("Reading");
   for line in bufreader.lines() {
@@ -24,8 +24,11 @@ pub fn solve_v4()  {
       break;
     }
     let value = components[1].parse::<f32>().unwrap();
+    let name = components[0];
+    let name = names.normalize.apply(name.as_bytes(), &mut name_buffer).unwrap_or(name);
+    let name = &name[..checked_name_len(name.as_bytes(), names.policy).unwrap()];
     let value_entry = station_values
-      .entry(components[0].to_string())
+      .entry(name.to_string())
       .or_insert(Record {
         total: 0.0,
         min: f32::INFINITY,
@@ -39,9 +42,9 @@ pub fn solve_v4()  {
   }
   println!("Summing");
   let mut station_keys: Vec<_> = station_values.keys().collect();
-  station_keys.sort();
+  names.order.sort(&mut station_keys);
   let mut result = String::new();
-  result.push_str("{");
+  result.push('{');
   for (i, key) in station_keys.into_iter().enumerate() {
     let values = station_values.get(key).unwrap();
     let min = values.min;
@@ -53,5 +56,5 @@ pub fn solve_v4()  {
     result.push_str(&format!("{}={:.1}/{:.1}/{:.1}", key, min, avg, max));
   }
   result.push_str("}\n");
-  write_string_to_output(&result);
+  result
 }
@@ -1,12 +1,14 @@
 use std::{
   collections::HashMap,
-  fs::{self, File},
-  io::{BufRead, BufReader, Read},
+  fs::File,
+  io::Read,
+  path::Path,
 };
 
-use ordered_float::NotNan;
-
-use crate::{write_string_to_output, MEASUREMENTS};
+use crate::{
+  names::{checked_name_len, name_str, NameConfig},
+  MAX_STATION_NAME_LEN,
+};
 
 struct Record {
   total: f32,
@@ -81,26 +83,29 @@ fn parse_temperature(reader: &mut RawBufReader) -> f32 {
 
 /// 1. custom bufreader removing allocations
 /// 2. custom float parsing
-pub fn solve_v5() {
+pub fn solve_v5(input: &Path, names: &NameConfig) -> String {
   let mut station_values: HashMap<Vec<u8>, Record> = HashMap::new();
-  let file = File::open(MEASUREMENTS).unwrap();
+  let file = File::open(input).unwrap();
   let mut bufreader = RawBufReader::new(file);
 
   println!("Reading");
 
+  let mut station_name_buffer = Vec::with_capacity(MAX_STATION_NAME_LEN);
+  let mut name_buffer = String::new();
   while bufreader.has_next_line() {
     let mut c = bufreader.next_char();
-    let mut station_name_buffer: [u8; 100] = [0; 100];
-    let mut station_name_buffer_ind = 0;
+    station_name_buffer.clear();
     while c != b';' {
-      station_name_buffer[station_name_buffer_ind] = c;
-      station_name_buffer_ind += 1;
+      station_name_buffer.push(c);
       c = bufreader.next_char();
     }
 
     let value = parse_temperature(&mut bufreader);
 
-    let station_name = &station_name_buffer[..station_name_buffer_ind];
+    let station_name = &station_name_buffer[..];
+    let station_name =
+      names.normalize.apply(station_name, &mut name_buffer).map_or(station_name, str::as_bytes);
+    let station_name = &station_name[..checked_name_len(station_name, names.policy).unwrap()];
     let value_entry =
       station_values
         .entry(station_name.to_vec())
@@ -117,9 +122,9 @@ pub fn solve_v5() {
   }
   println!("Summing");
   let mut station_keys: Vec<_> = station_values.keys().collect();
-  station_keys.sort();
+  names.order.sort(&mut station_keys);
   let mut result = String::new();
-  result.push_str("{");
+  result.push('{');
   for (i, key) in station_keys.into_iter().enumerate() {
     let values = station_values.get(key).unwrap();
     let min = values.min;
@@ -130,12 +135,12 @@ pub fn solve_v5() {
     }
     result.push_str(&format!(
       "{}={:.1}/{:.1}/{:.1}",
-      std::str::from_utf8(key).unwrap(),
+      name_str(key).unwrap(),
       min,
       avg,
       max
     ));
   }
   result.push_str("}\n");
-  write_string_to_output(&result);
+  result
 }
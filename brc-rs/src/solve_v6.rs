@@ -1,13 +1,13 @@
 use std::{
-  collections::HashMap,
-  fs::{self, File},
-  io::{BufRead, BufReader, Read},
+  fs::File,
+  io::Read,
+  path::Path,
 };
 
-use ordered_float::NotNan;
 
 use crate::{
-  write_string_to_output, MAX_STATION_NAMES, MAX_STATION_NAME_LEN, MEASUREMENTS,
+  names::{checked_name_len, name_str, NameConfig},
+  MAX_STATION_NAMES, MAX_STATION_NAME_LEN,
 };
 
 #[derive(Copy, Clone)]
@@ -127,8 +127,8 @@ fn fnv_hash(value: &[u8]) -> usize {
   let mut result = FNV_OFFSET_BASIS;
 
   for b in value {
-    result = result ^ *b as u64;
-    result *= FNV_PRIME;
+    result ^= *b as u64;
+    result = result.wrapping_mul(FNV_PRIME);
   }
 
   result as usize
@@ -160,9 +160,7 @@ impl FixedSizeMap {
           start: name_start,
           end: name_end,
         };
-        for i in 0..name.len() {
-          self.names[i + name_start] = name[i];
-        }
+        self.names[name_start..name_end].copy_from_slice(name);
         self.last_name_idx = name_end;
         let kvpair = MapKvPair {
           key,
@@ -171,7 +169,7 @@ impl FixedSizeMap {
         self.entries[idx] = Some(kvpair);
         return idx;
       }
-      let mut entry = self.entries[idx].unwrap();
+      let entry = self.entries[idx].unwrap();
 
       // linear probing
       if &self.names[entry.key.start..entry.key.end] != name {
@@ -210,26 +208,29 @@ impl FixedSizeMap {
 }
 
 /// custom hashmap
-pub fn solve_v6() {
+pub fn solve_v6(input: &Path, names: &NameConfig) -> String {
   let mut station_values = FixedSizeMap::new();
-  let file = File::open(MEASUREMENTS).unwrap();
+  let file = File::open(input).unwrap();
   let mut bufreader = RawBufReader::new(file);
 
   println!("Reading");
 
+  let mut station_name_buffer = Vec::with_capacity(MAX_STATION_NAME_LEN);
+  let mut name_buffer = String::new();
   while bufreader.has_next_line() {
     let mut c = bufreader.next_char();
-    let mut station_name_buffer: [u8; 100] = [0; 100];
-    let mut station_name_buffer_ind = 0;
+    station_name_buffer.clear();
     while c != b';' {
-      station_name_buffer[station_name_buffer_ind] = c;
-      station_name_buffer_ind += 1;
+      station_name_buffer.push(c);
       c = bufreader.next_char();
     }
 
     let value = parse_temperature(&mut bufreader);
 
-    let station_name = &station_name_buffer[..station_name_buffer_ind];
+    let station_name = &station_name_buffer[..];
+    let station_name =
+      names.normalize.apply(station_name, &mut name_buffer).map_or(station_name, str::as_bytes);
+    let station_name = &station_name[..checked_name_len(station_name, names.policy).unwrap()];
     let value_idx = station_values.get_or_insert(station_name);
     let value_entry = &mut station_values.entries[value_idx].as_mut().unwrap().value;
     value_entry.total += value;
@@ -239,9 +240,9 @@ pub fn solve_v6() {
   }
   println!("Summing");
   let mut station_keys: Vec<_> = station_values.keys();
-  station_keys.sort();
+  names.order.sort(&mut station_keys);
   let mut result = String::new();
-  result.push_str("{");
+  result.push('{');
   for (i, key) in station_keys.iter().enumerate() {
     let values = station_values.get(key);
     let min = values.min;
@@ -252,12 +253,12 @@ pub fn solve_v6() {
     }
     result.push_str(&format!(
       "{}={:.1}/{:.1}/{:.1}",
-      std::str::from_utf8(key).unwrap(),
+      name_str(key).unwrap(),
       min,
       avg,
       max
     ));
   }
   result.push_str("}\n");
-  write_string_to_output(&result);
+  result
 }
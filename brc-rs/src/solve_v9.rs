@@ -1,9 +1,10 @@
-use std::fs::File;
+use std::{fs::File, path::Path};
 
 use memmap2::{Mmap, MmapOptions};
 
 use crate::{
-  write_string_to_output, MAX_STATION_NAMES, MAX_STATION_NAME_LEN, MEASUREMENTS,
+  names::{checked_name_len, name_str, NameConfig},
+  MAX_STATION_NAMES, MAX_STATION_NAME_LEN,
 };
 
 #[derive(Copy, Clone, Debug)]
@@ -126,7 +127,7 @@ fn fnv_hash(value: &[u8]) -> usize {
   let l = value.len().min(8);
   key[..l].copy_from_slice(&value[..l]);
   key[0] ^= value.len() as u8;
-  (u64::from_le_bytes(key) * FNV_PRIME) as usize
+  u64::from_le_bytes(key).wrapping_mul(FNV_PRIME) as usize
 }
 
 struct FixedSizeMap {
@@ -154,9 +155,7 @@ impl FixedSizeMap {
         let name_end = self.last_name_idx + name.len();
         cur_entry.key.start = name_start;
         cur_entry.key.end = name_end;
-        for i in 0..name.len() {
-          self.names[i + name_start] = name[i];
-        }
+        self.names[name_start..name_end].copy_from_slice(name);
         self.last_name_idx = name_end;
         return idx;
       }
@@ -199,26 +198,29 @@ impl FixedSizeMap {
 }
 
 /// remove options in hashmap (as_mut() apparently taking 3% of time?)
-pub fn solve_v9() {
+pub fn solve_v9(input: &Path, names: &NameConfig) -> String {
   let mut station_values = FixedSizeMap::new();
-  let file = File::open(MEASUREMENTS).unwrap();
+  let file = File::open(input).unwrap();
   let mut bufreader = RawBufReader::new(file);
 
   println!("Reading");
 
+  let mut station_name_buffer = Vec::with_capacity(MAX_STATION_NAME_LEN);
+  let mut name_buffer = String::new();
   while bufreader.has_next_line() {
     let mut c = bufreader.next_char();
-    let mut station_name_buffer: [u8; 100] = [0; 100];
-    let mut station_name_buffer_ind = 0;
+    station_name_buffer.clear();
     while c != b';' {
-      station_name_buffer[station_name_buffer_ind] = c;
-      station_name_buffer_ind += 1;
+      station_name_buffer.push(c);
       c = bufreader.next_char();
     }
 
     let value = parse_temperature(&mut bufreader);
 
-    let station_name = &station_name_buffer[..station_name_buffer_ind];
+    let station_name = &station_name_buffer[..];
+    let station_name =
+      names.normalize.apply(station_name, &mut name_buffer).map_or(station_name, str::as_bytes);
+    let station_name = &station_name[..checked_name_len(station_name, names.policy).unwrap()];
     let value_idx = station_values.get_or_insert(station_name);
     let value_entry = &mut station_values.entries[value_idx].value;
     value_entry.total += value;
@@ -228,9 +230,9 @@ pub fn solve_v9() {
   }
   println!("Summing");
   let mut station_keys: Vec<_> = station_values.keys();
-  station_keys.sort();
+  names.order.sort(&mut station_keys);
   let mut result = String::new();
-  result.push_str("{");
+  result.push('{');
   for (i, key) in station_keys.iter().enumerate() {
     let values = station_values.get(key);
     let min = values.min as f32 / 10.0;
@@ -241,12 +243,12 @@ pub fn solve_v9() {
     }
     result.push_str(&format!(
       "{}={:.1}/{:.1}/{:.1}",
-      std::str::from_utf8(key).unwrap(),
+      name_str(key).unwrap(),
       min,
       avg,
       max
     ));
   }
   result.push_str("}\n");
-  write_string_to_output(&result);
+  result
 }
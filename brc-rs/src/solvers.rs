@@ -0,0 +1,141 @@
+//! One entry point for every solver version, so the cli, benchmarks and tests
+//! can run any of them on any input.
+
+use std::{
+  fmt,
+  path::{Path, PathBuf},
+};
+
+use clap::ValueEnum;
+
+use crate::{
+  affinity::{ThreadConfig, ThreadPlan},
+  format::InputFormat,
+  mmap::MmapConfig,
+  names::NameConfig,
+  profile::REPO_WEATHER_STATIONS,
+  reader::ReaderConfig,
+  solve_v1::solve_v1,
+  solve_v10::solve_v10,
+  solve_v11::solve_v11,
+  solve_v12::solve_v12,
+  solve_v13::solve_v13,
+  solve_v14::solve_v14,
+  solve_v15::solve_v15,
+  solve_v2::solve_v2,
+  solve_v3::solve_v3,
+  solve_v4::solve_v4,
+  solve_v5::solve_v5,
+  solve_v6::solve_v6,
+  solve_v7::solve_v7,
+  solve_v8::solve_v8,
+  solve_v9::solve_v9,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, ValueEnum)]
+pub enum Solver {
+  V1,
+  V2,
+  V3,
+  V4,
+  V5,
+  V6,
+  V7,
+  V8,
+  V9,
+  V10,
+  V11,
+  V12,
+  V13,
+  V14,
+  V15,
+}
+
+impl Solver {
+  pub const ALL: [Solver; 15] = [
+    Solver::V1,
+    Solver::V2,
+    Solver::V3,
+    Solver::V4,
+    Solver::V5,
+    Solver::V6,
+    Solver::V7,
+    Solver::V8,
+    Solver::V9,
+    Solver::V10,
+    Solver::V11,
+    Solver::V12,
+    Solver::V13,
+    Solver::V14,
+    Solver::V15,
+  ];
+}
+
+impl fmt::Display for Solver {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.write_str(self.to_possible_value().unwrap().get_name())
+  }
+}
+
+/// settings only some of the solvers look at
+#[derive(Clone, Debug)]
+pub struct SolverConfig {
+  /// threads for v15
+  pub plan: ThreadPlan,
+  /// input reader for v15
+  pub reader: ReaderConfig,
+  /// mmap tuning for v15
+  pub mmap: MmapConfig,
+  /// station catalog for v13's perfect hash
+  pub catalog: PathBuf,
+  /// how every solver checks and sorts station names
+  pub names: NameConfig,
+  /// comments and header lines, only for v10 and v15
+  pub format: InputFormat,
+}
+
+impl Default for SolverConfig {
+  fn default() -> Self {
+    Self {
+      plan: ThreadConfig::default().resolve().unwrap(),
+      reader: ReaderConfig::default(),
+      mmap: MmapConfig::default(),
+      catalog: PathBuf::from(REPO_WEATHER_STATIONS),
+      names: NameConfig::default(),
+      format: InputFormat::default(),
+    }
+  }
+}
+
+/// runs `solver` on the measurements in `input` and returns its output
+pub fn solve(solver: Solver, input: &Path, config: &SolverConfig) -> String {
+  assert!(
+    config.format.is_plain() || matches!(solver, Solver::V10 | Solver::V15),
+    "{} only reads plain name;temperature lines, use v10 or v15 for other formats",
+    solver
+  );
+  match solver {
+    Solver::V1 => solve_v1(input, &config.names),
+    Solver::V2 => solve_v2(input, &config.names),
+    Solver::V3 => solve_v3(input, &config.names),
+    Solver::V4 => solve_v4(input, &config.names),
+    Solver::V5 => solve_v5(input, &config.names),
+    Solver::V6 => solve_v6(input, &config.names),
+    Solver::V7 => solve_v7(input, &config.names),
+    Solver::V8 => solve_v8(input, &config.names),
+    Solver::V9 => solve_v9(input, &config.names),
+    Solver::V10 => solve_v10(input, &config.names, &config.format),
+    Solver::V11 => solve_v11(input, &config.names),
+    Solver::V12 => solve_v12(input, &config.names),
+    Solver::V13 => solve_v13(input, &config.catalog, &config.names),
+    Solver::V14 => solve_v14(input, &config.names),
+    Solver::V15 => solve_v15(
+      input,
+      &config.plan,
+      &config.reader,
+      &config.mmap,
+      &config.names,
+      &config.format,
+    ),
+  }
+}
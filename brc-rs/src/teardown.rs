@@ -0,0 +1,67 @@
+//! Skipping the teardown of a huge mapping: the work happens in a forked
+//! child and the parent exits as soon as the child has written the results,
+//! leaving the child to unmap everything and exit on its own time.
+
+use std::{
+  io,
+  sync::atomic::{AtomicI32, Ordering},
+};
+
+/// write end of the pipe to the waiting parent, -1 if we didn't fork
+static RESULTS_WRITTEN_FD: AtomicI32 = AtomicI32::new(-1);
+
+/// Forks and only returns in the child. The parent waits until the child
+/// calls `results_written` and exits right away, or exits with the child's
+/// status if it dies before that.
+pub fn fork_for_teardown() -> io::Result<()> {
+  let mut fds = [0; 2];
+  if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+    return Err(io::Error::last_os_error());
+  }
+  let [read_fd, write_fd] = fds;
+
+  match unsafe { libc::fork() } {
+    -1 => Err(io::Error::last_os_error()),
+    0 => {
+      unsafe { libc::close(read_fd) };
+      RESULTS_WRITTEN_FD.store(write_fd, Ordering::SeqCst);
+      Ok(())
+    }
+    child => {
+      unsafe { libc::close(write_fd) };
+      let mut byte = 0u8;
+      let read = unsafe { libc::read(read_fd, &mut byte as *mut u8 as *mut libc::c_void, 1) };
+      if read == 1 {
+        std::process::exit(0);
+      }
+      // the child exited (or crashed) without writing results
+      let mut status = 0;
+      unsafe { libc::waitpid(child, &mut status, 0) };
+      let code = if libc::WIFEXITED(status) {
+        libc::WEXITSTATUS(status)
+      } else {
+        1
+      };
+      std::process::exit(code);
+    }
+  }
+}
+
+/// Drops `value`, unless we forked: then it's leaked and only freed when the
+/// child exits, which is after the parent is gone.
+pub fn leak_if_forked<T>(value: T) {
+  if RESULTS_WRITTEN_FD.load(Ordering::SeqCst) >= 0 {
+    std::mem::forget(value);
+  }
+}
+
+/// lets the parent exit, no-op unless `fork_for_teardown` was called
+pub fn results_written() {
+  let fd = RESULTS_WRITTEN_FD.swap(-1, Ordering::SeqCst);
+  if fd >= 0 {
+    unsafe {
+      libc::write(fd, [1u8].as_ptr() as *const libc::c_void, 1);
+      libc::close(fd);
+    }
+  }
+}
@@ -0,0 +1,270 @@
+//! io_uring input: one thread keeps several large reads in flight into a ring
+//! of buffers and hands every filled buffer to the parser threads.
+//!
+//! Buffers are cut at fixed offsets, so lines crossing a buffer boundary get
+//! split. Parsers only handle the lines fully inside their buffer and keep the
+//! partial first and last line, which are glued back together and parsed once
+//! everything else is done.
+
+use std::{
+  fs::File,
+  io,
+  ops::Range,
+  os::fd::AsRawFd,
+  sync::{mpsc, Mutex},
+  thread,
+};
+
+use io_uring::{opcode, types, IoUring};
+use memchr::{memchr, memrchr};
+
+use crate::affinity::ThreadPlan;
+
+pub const BUFFER_SIZE: usize = 4 * 1024 * 1024;
+
+/// the `index`th buffer of the file, filled with `len` bytes from `offset`
+struct Filled {
+  index: usize,
+  offset: usize,
+  buffer: Vec<u8>,
+  len: usize,
+}
+
+/// a read the kernel is working on
+struct InFlight {
+  index: usize,
+  offset: usize,
+  buffer: Vec<u8>,
+  want: usize,
+  filled: usize,
+}
+
+/// the partial lines at the edges of buffer `index`
+struct Fragments {
+  index: usize,
+  /// everything up to and including the first newline (or the whole buffer)
+  head: Vec<u8>,
+  /// whether `head` finishes a line
+  head_has_newline: bool,
+  /// everything after the last newline
+  tail: Vec<u8>,
+  /// where `tail` starts in the file
+  tail_offset: usize,
+}
+
+/// splits `data` into the partial first line, the complete lines and the
+/// partial last line
+fn split_partial_lines(data: &[u8], is_first: bool) -> (&[u8], bool, &[u8], &[u8]) {
+  let first_newline = match memchr(b'\n', data) {
+    Some(ind) => ind,
+    None => return (data, false, &[], &[]),
+  };
+  let last_newline = memrchr(b'\n', data).unwrap();
+  let lines_start = if is_first { 0 } else { first_newline + 1 };
+  (
+    &data[..lines_start],
+    true,
+    &data[lines_start..last_newline + 1],
+    &data[last_newline + 1..],
+  )
+}
+
+/// Runs `process` over line aligned chunks of `file` from byte `start` (the
+/// start of a line) on `plan.threads` parser threads while the calling thread
+/// drives the reads, and returns every parser's state or the first error.
+/// `process` gets every chunk with its offset in the file.
+pub fn read_uring<S, I, P>(
+  file: &File,
+  start: usize,
+  plan: &ThreadPlan,
+  init: I,
+  process: P,
+) -> io::Result<Vec<S>>
+where
+  S: Send,
+  I: Fn() -> S + Sync,
+  P: Fn(&[u8], usize, &mut S) -> io::Result<()> + Sync,
+{
+  let len = file.metadata()?.len() as usize;
+  // enough for every parser to have one buffer while the rest are being read
+  let num_buffers = 2 * plan.threads + 2;
+  let (filled_tx, filled_rx) = mpsc::sync_channel::<Filled>(num_buffers);
+  let filled_rx = Mutex::new(filled_rx);
+  let (free_tx, free_rx) = mpsc::channel::<Vec<u8>>();
+  for _ in 0..num_buffers {
+    free_tx.send(vec![0u8; BUFFER_SIZE]).unwrap();
+  }
+
+  let (mut states, mut fragments) = thread::scope(|s| -> io::Result<_> {
+    let parsers: Vec<_> = (0..plan.threads)
+      .map(|worker| {
+        let (filled_rx, free_tx, init, process) = (&filled_rx, free_tx.clone(), &init, &process);
+        s.spawn(move || -> io::Result<(S, Vec<Fragments>)> {
+          plan.pin_worker(worker)?;
+          let mut state = init();
+          let mut fragments = Vec::new();
+          loop {
+            let filled = filled_rx.lock().unwrap().recv();
+            let Ok(filled) = filled else {
+              break;
+            };
+            let data = &filled.buffer[..filled.len];
+            let (head, head_has_newline, lines, tail) =
+              split_partial_lines(data, filled.index == 0);
+            process(lines, filled.offset + head.len(), &mut state)?;
+            fragments.push(Fragments {
+              index: filled.index,
+              head: head.to_vec(),
+              head_has_newline,
+              tail: tail.to_vec(),
+              tail_offset: filled.offset + filled.len - tail.len(),
+            });
+            // the reader is gone once everything has been read
+            let _ = free_tx.send(filled.buffer);
+          }
+          Ok((state, fragments))
+        })
+      })
+      .collect();
+    // only parsers hand buffers back from here on
+    drop(free_tx);
+
+    let read_result = submit_reads(file, start..len, num_buffers, filled_tx, &free_rx);
+    let mut states = Vec::new();
+    let mut fragments = Vec::new();
+    for parser in parsers {
+      let (state, parser_fragments) = parser.join().unwrap()?;
+      states.push(state);
+      fragments.extend(parser_fragments);
+    }
+    read_result?;
+    Ok((states, fragments))
+  })?;
+
+  // glue the lines crossing buffer boundaries back together
+  fragments.sort_by_key(|f| f.index);
+  let mut line = Vec::new();
+  let mut line_offset = start;
+  for fragment in &fragments {
+    line.extend_from_slice(&fragment.head);
+    if fragment.head_has_newline {
+      if !line.is_empty() {
+        process(&line, line_offset, &mut states[0])?;
+      }
+      line.clear();
+      line.extend_from_slice(&fragment.tail);
+      line_offset = fragment.tail_offset;
+    }
+  }
+  // last line without a trailing newline
+  if !line.is_empty() {
+    process(&line, line_offset, &mut states[0])?;
+  }
+  Ok(states)
+}
+
+fn push_read(ring: &mut IoUring, fd: types::Fd, slot: usize, read: &mut InFlight) {
+  let entry = opcode::Read::new(
+    fd,
+    unsafe { read.buffer.as_mut_ptr().add(read.filled) },
+    (read.want - read.filled) as u32,
+  )
+  .offset((read.offset + read.filled) as u64)
+  .build()
+  .user_data(slot as u64);
+  // the queue has a slot for every buffer, and the buffer stays in `in_flight`
+  // until its completion has been reaped
+  unsafe { ring.submission().push(&entry) }.expect("submission queue is full");
+}
+
+/// reads `range` of the file into buffers from `free_rx`, keeping up to
+/// `queue_depth` reads in flight, and sends them to the parsers in any order
+fn submit_reads(
+  file: &File,
+  range: Range<usize>,
+  queue_depth: usize,
+  filled_tx: mpsc::SyncSender<Filled>,
+  free_rx: &mpsc::Receiver<Vec<u8>>,
+) -> io::Result<()> {
+  let mut ring = IoUring::new(queue_depth.next_power_of_two() as u32)?;
+  let fd = types::Fd(file.as_raw_fd());
+  let mut in_flight: Vec<Option<InFlight>> = (0..queue_depth).map(|_| None).collect();
+  let mut next_offset = range.start;
+  let mut next_index = 0;
+
+  let result = (|| -> io::Result<()> {
+    loop {
+      // queue reads while there are free buffers
+      while next_offset < range.end {
+        let Some(slot) = in_flight.iter().position(|r| r.is_none()) else {
+          break;
+        };
+        let is_idle = in_flight.iter().all(|r| r.is_none());
+        let buffer = if is_idle {
+          match free_rx.recv() {
+            Ok(buffer) => buffer,
+            Err(_) => return Err(io::Error::other("parser threads exited early")),
+          }
+        } else {
+          match free_rx.try_recv() {
+            Ok(buffer) => buffer,
+            Err(_) => break,
+          }
+        };
+        let want = BUFFER_SIZE.min(range.end - next_offset);
+        let read = in_flight[slot].insert(InFlight {
+          index: next_index,
+          offset: next_offset,
+          buffer,
+          want,
+          filled: 0,
+        });
+        push_read(&mut ring, fd, slot, read);
+        next_offset += want;
+        next_index += 1;
+      }
+
+      if in_flight.iter().all(|r| r.is_none()) {
+        return Ok(());
+      }
+      ring.submit_and_wait(1)?;
+      let completions: Vec<(usize, i32)> = ring
+        .completion()
+        .map(|c| (c.user_data() as usize, c.result()))
+        .collect();
+      for (slot, result) in completions {
+        if result < 0 {
+          return Err(io::Error::from_raw_os_error(-result));
+        }
+        let read = in_flight[slot].as_mut().unwrap();
+        if result == 0 {
+          return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        read.filled += result as usize;
+        if read.filled < read.want {
+          // short read, ask for the rest
+          push_read(&mut ring, fd, slot, read);
+          continue;
+        }
+        let read = in_flight[slot].take().unwrap();
+        let filled = Filled {
+          index: read.index,
+          offset: read.offset,
+          buffer: read.buffer,
+          len: read.want,
+        };
+        if filled_tx.send(filled).is_err() {
+          return Err(io::Error::other("parser threads exited early"));
+        }
+      }
+    }
+  })();
+
+  if result.is_err() {
+    // the kernel may still write into these, never free them
+    for read in in_flight.into_iter().flatten() {
+      std::mem::forget(read.buffer);
+    }
+  }
+  result
+}
@@ -0,0 +1,449 @@
+//! Runs every solver on random valid measurement files and checks them
+//! against a straightforward reference, shrinking to a minimal file when one
+//! disagrees.
+
+use std::{
+  collections::BTreeMap,
+  fs,
+  panic::{catch_unwind, AssertUnwindSafe},
+  path::PathBuf,
+  sync::atomic::{AtomicUsize, Ordering},
+  thread,
+};
+
+use brc_rs::{
+  affinity::ThreadPlan,
+  format::{DecimalSeparator, InputFormat, NumberFormat},
+  names::{NameConfig, NamePolicy, NormalForm, Normalize},
+  reader::{ReaderConfig, ReaderKind},
+  solvers::{solve, Solver, SolverConfig},
+  MAX_STATION_NAME_LEN,
+};
+use proptest::{prelude::*, test_runner::TestCaseError};
+
+const SOLVER_STACK_SIZE: usize = 256 * 1024 * 1024;
+
+/// min, sum and max in tenths of a degree, and the number of measurements
+#[derive(Debug)]
+struct Expected {
+  min: i32,
+  sum: i64,
+  max: i32,
+  count: i64,
+}
+
+/// Names of 1 to `MAX_STATION_NAME_LEN` bytes of any utf-8 but `;` and
+/// control characters, weighted towards short ascii ones like real stations.
+/// None start with U+FEFF, v10 and v15 take that as a byte order mark when
+/// it's the first name in the file.
+fn station_name() -> impl Strategy<Value = String> {
+  prop_oneof![
+    3 => "[a-zA-Z][a-z -]{0,20}",
+    1 => "[^;\\p{Cc}]{1,40}",
+  ]
+  .prop_filter("name too long", |name| name.len() <= MAX_STATION_NAME_LEN)
+  .prop_filter("byte order mark", |name| !name.starts_with('\u{feff}'))
+}
+
+/// rows as (station, tenths of a degree)
+fn measurements() -> impl Strategy<Value = Vec<(String, i32)>> {
+  prop::collection::btree_set(station_name(), 1..20)
+    .prop_map(|names| names.into_iter().collect::<Vec<_>>())
+    .prop_flat_map(|names| {
+      let station = prop::sample::select(names);
+      prop::collection::vec((station, -999..=999i32), 1..300)
+    })
+}
+
+fn format_tenths(tenths: i32) -> String {
+  let sign = if tenths < 0 { "-" } else { "" };
+  format!("{}{}.{}", sign, tenths.abs() / 10, tenths.abs() % 10)
+}
+
+/// writes `rows` to a file of its own, solvers only take paths
+fn write_input(rows: &[(String, i32)]) -> PathBuf {
+  static NEXT_FILE: AtomicUsize = AtomicUsize::new(0);
+  let path = std::env::temp_dir().join(format!(
+    "brc-differential-{}-{}.txt",
+    std::process::id(),
+    NEXT_FILE.fetch_add(1, Ordering::Relaxed)
+  ));
+  let mut contents = String::new();
+  for (name, tenths) in rows {
+    contents.push_str(&format!("{};{}\n", name, format_tenths(*tenths)));
+  }
+  fs::write(&path, contents).unwrap();
+  path
+}
+
+fn expected(rows: &[(String, i32)]) -> BTreeMap<String, Expected> {
+  let mut stations = BTreeMap::new();
+  for (name, tenths) in rows {
+    let entry = stations.entry(name.clone()).or_insert(Expected {
+      min: i32::MAX,
+      sum: 0,
+      max: i32::MIN,
+      count: 0,
+    });
+    entry.min = entry.min.min(*tenths);
+    entry.max = entry.max.max(*tenths);
+    entry.sum += *tenths as i64;
+    entry.count += 1;
+  }
+  stations
+}
+
+fn parse_value(value: &str) -> Result<f64, TestCaseError> {
+  value
+    .parse()
+    .map_err(|_| TestCaseError::fail(format!("invalid value {:?}", value)))
+}
+
+/// Walks `output` station by station, names can contain `, ` and `=` so
+/// they're matched against the expected ones instead of split on. Stations
+/// have to be in java's order, by utf-16 code units.
+fn check_output(
+  solver: Solver,
+  output: &str,
+  expected: &BTreeMap<String, Expected>,
+) -> Result<(), TestCaseError> {
+  let mut expected: Vec<_> = expected.iter().collect();
+  expected.sort_by(|(a, _), (b, _)| a.encode_utf16().cmp(b.encode_utf16()));
+  let fail =
+    |what: String| TestCaseError::fail(format!("{}: {}\noutput: {:?}", solver, what, output));
+  let mut rest = output
+    .strip_prefix('{')
+    .ok_or_else(|| fail("missing {".to_string()))?;
+  for (i, (name, station)) in expected.into_iter().enumerate() {
+    if i != 0 {
+      rest = rest
+        .strip_prefix(", ")
+        .ok_or_else(|| fail(format!("missing separator before {:?}", name)))?;
+    }
+    rest = rest
+      .strip_prefix(name.as_str())
+      .and_then(|rest| rest.strip_prefix('='))
+      .ok_or_else(|| fail(format!("expected {:?} next", name)))?;
+    let end = rest.find([',', '}']).unwrap_or(rest.len());
+    let values: Vec<_> = rest[..end].split('/').collect();
+    rest = &rest[end..];
+    let [min, mean, max] = values[..] else {
+      return Err(fail(format!("expected min/mean/max for {:?}", name)));
+    };
+    let (min, mean, max) = (parse_value(min)?, parse_value(mean)?, parse_value(max)?);
+    prop_assert_eq!(min, station.min as f64 / 10.0, "{} min of {:?}", solver, name);
+    prop_assert_eq!(max, station.max as f64 / 10.0, "{} max of {:?}", solver, name);
+    // the solvers round in float, so a mean exactly halfway can go either way
+    let exact_mean = station.sum as f64 / station.count as f64 / 10.0;
+    prop_assert!(
+      (mean - exact_mean).abs() <= 0.05 + 1e-3,
+      "{} mean of {:?} is {}, expected {}",
+      solver,
+      name,
+      mean,
+      exact_mean
+    );
+  }
+  prop_assert_eq!(rest, "}\n", "{} trailing output", solver);
+  Ok(())
+}
+
+/// Every solver's output for `path`, `None` for the ones that panicked.
+/// Removes the file afterwards.
+fn solve_all(path: PathBuf, config: SolverConfig) -> [(Solver, Option<String>); 15] {
+  // the older solvers keep their maps on the stack, which doesn't fit in a
+  // test thread's
+  let outputs = thread::Builder::new()
+    .stack_size(SOLVER_STACK_SIZE)
+    .spawn({
+      let path = path.clone();
+      move || {
+        Solver::ALL.map(|solver| {
+          let output = catch_unwind(AssertUnwindSafe(|| solve(solver, &path, &config)));
+          (solver, output.ok())
+        })
+      }
+    })
+    .unwrap()
+    .join()
+    .unwrap();
+  fs::remove_file(&path).unwrap();
+  outputs
+}
+
+#[test]
+fn solvers_round_means_alike() {
+  // means of exactly x.x5, and negative means that round to zero
+  let rows = [
+    ("Hamburg", 1),
+    ("Hamburg", 2),
+    ("Oslo", -1),
+    ("Oslo", -2),
+    ("Bergen", -1),
+    ("Bergen", 0),
+    ("Bergen", 0),
+    ("Palembang", 995),
+    ("Palembang", 996),
+  ];
+  let rows: Vec<_> = rows.iter().map(|(name, tenths)| (name.to_string(), *tenths)).collect();
+  let expected = "{Bergen=-0.1/-0.0/0.0, Hamburg=0.1/0.2/0.2, Oslo=-0.2/-0.2/-0.1, \
+                  Palembang=99.5/99.6/99.6}\n";
+  for (solver, output) in solve_all(write_input(&rows), SolverConfig::default()) {
+    assert_eq!(output.as_deref(), Some(expected), "{}", solver);
+  }
+}
+
+#[test]
+fn solvers_apply_the_name_policy() {
+  // 200 and 103 bytes, both "ü" 50 times when cut to 100 bytes
+  let long = "ü".repeat(MAX_STATION_NAME_LEN);
+  let truncated = "ü".repeat(MAX_STATION_NAME_LEN / 2);
+  let longer = format!("{}abc", truncated);
+  let rows = vec![
+    ("Hamburg".to_string(), 120),
+    (long.clone(), -10),
+    (longer.clone(), 20),
+    (long.clone(), 40),
+  ];
+
+  let with_policy = |name_policy| {
+    let config = SolverConfig {
+      names: NameConfig {
+        policy: name_policy,
+        ..Default::default()
+      },
+      ..Default::default()
+    };
+    solve_all(write_input(&rows), config)
+  };
+  for (solver, output) in with_policy(NamePolicy::Reject) {
+    assert!(output.is_none(), "{} accepted a long name", solver);
+  }
+
+  let truncated_rows: Vec<_> = rows
+    .iter()
+    .map(|(name, tenths)| match name.len() > MAX_STATION_NAME_LEN {
+      true => (truncated.clone(), *tenths),
+      false => (name.clone(), *tenths),
+    })
+    .collect();
+  for (policy, rows) in [(NamePolicy::Truncate, &truncated_rows), (NamePolicy::Allow, &rows)] {
+    let expected = expected(rows);
+    for (solver, output) in with_policy(policy) {
+      let output = output.unwrap_or_else(|| panic!("{} panicked with {:?}", solver, policy));
+      check_output(solver, &output, &expected).unwrap();
+    }
+  }
+}
+
+#[test]
+fn readers_report_malformed_lines() {
+  // past the first io_uring buffer and read window
+  let rows: Vec<_> = (0..1_000_000).map(|i| (format!("station {}", i % 97), i % 999)).collect();
+  let path = write_input(&rows);
+  let mut contents = fs::read(&path).unwrap();
+  let near_end = contents.len() - 1000;
+  let blank_line = near_end + contents[near_end..].iter().position(|b| *b == b'\n').unwrap() + 1;
+  contents.insert(blank_line, b'\n');
+  fs::write(&path, contents).unwrap();
+
+  for reader in [ReaderKind::Mmap, ReaderKind::Read, ReaderKind::IoUring] {
+    let config = SolverConfig {
+      plan: ThreadPlan {
+        threads: 2,
+        cpus: Vec::new(),
+      },
+      reader: ReaderConfig {
+        kind: reader,
+        memory_budget: 16 * 1024 * 1024,
+      },
+      format: InputFormat {
+        trim: true,
+        ..Default::default()
+      },
+      ..Default::default()
+    };
+    let error = catch_unwind(|| solve(Solver::V15, &path, &config)).unwrap_err();
+    let error = error.downcast_ref::<String>().unwrap();
+    let expected = format!("missing ';' in line at byte {}", blank_line);
+    assert!(error.ends_with(&expected), "{:?} with {:?}", error, reader);
+  }
+  fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn readers_allow_long_names() {
+  // up to 342 bytes, longer than a window reads past its end
+  let rows: Vec<_> = (0..200_000)
+    .map(|i| {
+      let name = format!("{}{}", "long station name ".repeat(i as usize % 20), i % 89);
+      (name, (i * 37 % 1999) - 999)
+    })
+    .collect();
+  let path = write_input(&rows);
+  let expected = expected(&rows);
+  for reader in [ReaderKind::Mmap, ReaderKind::Read, ReaderKind::IoUring] {
+    let config = SolverConfig {
+      plan: ThreadPlan {
+        threads: 2,
+        cpus: Vec::new(),
+      },
+      reader: ReaderConfig {
+        kind: reader,
+        memory_budget: 16 * 1024 * 1024,
+      },
+      names: NameConfig {
+        policy: NamePolicy::Allow,
+        ..Default::default()
+      },
+      ..Default::default()
+    };
+    check_output(Solver::V15, &solve(Solver::V15, &path, &config), &expected).unwrap();
+  }
+  fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn solvers_normalize_names() {
+  let rows: Vec<_> = [
+    ("Z\u{fc}rich", 10),
+    ("Zu\u{308}rich", 20),
+    ("Z\u{dc}RICH", -30),
+    ("Hamburg", 120),
+    ("HAMBURG", 40),
+    ("\u{ff28}\u{ff41}\u{ff4d}\u{ff42}\u{ff55}\u{ff52}\u{ff47}", 50),
+    ("Petropavlovsk-Kamchatsky", 0),
+    ("PETROPAVLOVSK-KAMCHATSKY", -70),
+    // the bytes around `A` to `Z`
+    ("@AZ[`az{", 80),
+    ("@az[`AZ{", 90),
+  ]
+  .into_iter()
+  .map(|(name, tenths)| (name.to_string(), tenths))
+  .collect();
+  let normalized_rows: Vec<_> = [
+    ("z\u{fc}rich", 10),
+    ("z\u{fc}rich", 20),
+    ("z\u{fc}rich", -30),
+    ("hamburg", 120),
+    ("hamburg", 40),
+    ("hamburg", 50),
+    ("petropavlovsk-kamchatsky", 0),
+    ("petropavlovsk-kamchatsky", -70),
+    ("@az[`az{", 80),
+    ("@az[`az{", 90),
+  ]
+  .into_iter()
+  .map(|(name, tenths)| (name.to_string(), tenths))
+  .collect();
+  let config = SolverConfig {
+    names: NameConfig {
+      normalize: Normalize {
+        form: NormalForm::Nfkc,
+        case_fold: true,
+      },
+      ..Default::default()
+    },
+    ..Default::default()
+  };
+
+  let expected = expected(&normalized_rows);
+  for (solver, output) in solve_all(write_input(&rows), config) {
+    let output = output.unwrap_or_else(|| panic!("{} panicked", solver));
+    check_output(solver, &output, &expected).unwrap();
+  }
+}
+
+#[test]
+fn readers_handle_formatted_input() {
+  // more than one io_uring buffer, so lines cross buffer boundaries
+  let rows: Vec<_> = (0..400_000)
+    .map(|i| (format!("station {}", i % 97), (i * 37 % 1999) - 999))
+    .collect();
+  let mut contents =
+    b"\xef\xbb\xbfstation;temperature\n// exported by a spreadsheet;1.0\n".to_vec();
+  for (i, (name, tenths)) in rows.iter().enumerate() {
+    if i % 7 == 0 {
+      contents.extend_from_slice(b"//\n");
+    }
+    // longer than any measurement line, some of them cross window boundaries
+    if i % 5 == 0 {
+      contents.extend_from_slice(format!("// {}\n", "x".repeat(300)).as_bytes());
+    }
+    let padding = " ".repeat(i % 3);
+    let value = format_tenths(*tenths).replace('.', ",");
+    let line = format!("{}{}{};{}{}\r\n", padding, name, padding, padding, value);
+    contents.extend_from_slice(line.as_bytes());
+  }
+  let path = write_input(&[]);
+  fs::write(&path, contents).unwrap();
+
+  let expected = expected(&rows);
+  let format = InputFormat {
+    comment_prefix: Some("//".to_string()),
+    header_lines: 1,
+    trim: true,
+    number: NumberFormat::new(DecimalSeparator::Comma, Some(b'.')).unwrap(),
+    ..Default::default()
+  };
+  for (solver, reader) in [
+    (Solver::V10, ReaderKind::Mmap),
+    (Solver::V15, ReaderKind::Mmap),
+    (Solver::V15, ReaderKind::Read),
+    (Solver::V15, ReaderKind::IoUring),
+  ] {
+    let config = SolverConfig {
+      // small windows, so the read reader has many of them
+      plan: ThreadPlan {
+        threads: 2,
+        cpus: Vec::new(),
+      },
+      reader: ReaderConfig {
+        kind: reader,
+        memory_budget: 16 * 1024 * 1024,
+      },
+      format: format.clone(),
+      ..Default::default()
+    };
+    let output = solve(solver, &path, &config);
+    check_output(solver, &output, &expected).unwrap();
+  }
+  fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn readers_skip_a_byte_order_mark() {
+  // without a header the first measurement follows the mark right away
+  let path = write_input(&[]);
+  fs::write(&path, b"\xef\xbb\xbfHamburg;12.0\nOslo;1.0\n").unwrap();
+  for (solver, reader) in [
+    (Solver::V10, ReaderKind::Mmap),
+    (Solver::V15, ReaderKind::Mmap),
+    (Solver::V15, ReaderKind::Read),
+    (Solver::V15, ReaderKind::IoUring),
+  ] {
+    let config = SolverConfig {
+      reader: ReaderConfig {
+        kind: reader,
+        ..Default::default()
+      },
+      ..Default::default()
+    };
+    let output = solve(solver, &path, &config);
+    assert_eq!(output, "{Hamburg=12.0/12.0/12.0, Oslo=1.0/1.0/1.0}\n", "{} {:?}", solver, reader);
+  }
+  fs::remove_file(&path).unwrap();
+}
+
+proptest! {
+  #![proptest_config(ProptestConfig::with_cases(64))]
+
+  #[test]
+  fn solvers_agree_with_reference(rows in measurements()) {
+    let expected = expected(&rows);
+    for (solver, output) in solve_all(write_input(&rows), SolverConfig::default()) {
+      let output = output.ok_or_else(|| TestCaseError::fail(format!("{} panicked", solver)))?;
+      check_output(solver, &output, &expected)?;
+    }
+  }
+}
@@ -0,0 +1,279 @@
+//! The samples from the java implementation through the solvers that take
+//! their input from memory (v10 to v15). Small enough to also run under miri:
+//!
+//!   cargo +nightly miri test --test samples
+
+use std::{collections::HashMap, thread};
+
+use brc_rs::{
+  affinity::ThreadPlan,
+  format::{DecimalSeparator, InputFormat, NumberFormat},
+  names::NameConfig,
+  perfect_hash::PerfectHash,
+  solve_v10::solve_v10_input,
+  solve_v11::solve_v11_input,
+  solve_v12::solve_v12_input,
+  solve_v13::solve_v13_input,
+  solve_v14::solve_v14_input,
+  solve_v15::solve_v15_input,
+};
+
+macro_rules! samples {
+  ($($name:literal),* $(,)?) => {
+    [$((
+      $name,
+      include_bytes!(concat!("../../src/test/resources/samples/", $name, ".txt")) as &[u8],
+      include_str!(concat!("../../src/test/resources/samples/", $name, ".out")),
+    )),*]
+  };
+}
+
+const SAMPLES: [(&str, &[u8], &str); 11] = samples![
+  "measurements-1",
+  "measurements-2",
+  "measurements-3",
+  "measurements-10",
+  "measurements-20",
+  "measurements-10000-unique-keys",
+  "measurements-boundaries",
+  "measurements-complex-utf8",
+  "measurements-dot",
+  "measurements-short",
+  "measurements-shortest",
+];
+
+/// the samples small enough for miri, which interprets every byte
+fn samples() -> impl Iterator<Item = (&'static str, &'static [u8], &'static str)> {
+  SAMPLES
+    .into_iter()
+    .filter(|(_, input, _)| !cfg!(miri) || input.len() < 10_000)
+}
+
+/// `{name=min/mean/max, ...}` as names and values, with `fields` values per
+/// name. Names may contain ", " themselves, so a piece only ends an entry once
+/// it ends in that many numbers.
+fn parse_output(output: &str, fields: usize) -> Vec<(String, Vec<f64>)> {
+  let inner = output.strip_prefix('{').unwrap().strip_suffix("}\n").unwrap();
+  let mut entries = Vec::new();
+  let mut name = String::new();
+  for piece in inner.split(", ") {
+    name.push_str(piece);
+    let values = name.rsplit_once('=').and_then(|(station, values)| {
+      let values: Vec<f64> = values.split('/').map(|v| v.parse().ok()).collect::<Option<_>>()?;
+      (values.len() == fields).then(|| (station.to_string(), values))
+    });
+    match values {
+      Some(entry) => {
+        entries.push(entry);
+        name.clear();
+      }
+      None => name.push_str(", "),
+    }
+  }
+  assert!(name.is_empty(), "{:?}", output);
+  entries
+}
+
+/// Same stations with the same min and max. The solvers round the mean in
+/// floating point and can be 0.1 off the java reference, which rounds half
+/// up.
+fn assert_matches(output: &str, expected: &str, sample: &str) {
+  let (output, expected) = (parse_output(output, 3), parse_output(expected, 3));
+  assert_eq!(output.len(), expected.len(), "{}", sample);
+  for ((name, values), (expected_name, expected_values)) in output.iter().zip(&expected) {
+    assert_eq!(name, expected_name, "{}", sample);
+    assert_eq!(values[0], expected_values[0], "{} {}", sample, name);
+    assert!((values[1] - expected_values[1]).abs() < 0.11, "{} {}", sample, name);
+    assert_eq!(values[2], expected_values[2], "{} {}", sample, name);
+  }
+}
+
+#[test]
+fn v10_matches_samples() {
+  for (name, input, expected) in samples() {
+    let output = solve_v10_input(input, &NameConfig::default(), &InputFormat::default());
+    assert_matches(&output, expected, name);
+  }
+}
+
+#[test]
+fn v11_to_v14_match_samples() {
+  // their maps are on the stack, which doesn't fit in a test thread's
+  thread::Builder::new()
+    .stack_size(256 * 1024 * 1024)
+    .spawn(|| {
+      let names = NameConfig::default();
+      for (name, input, expected) in samples() {
+        assert_matches(&solve_v11_input(input, &names), expected, name);
+        assert_matches(&solve_v12_input(input, &names), expected, name);
+        assert_matches(&solve_v14_input(input, &names), expected, name);
+
+        // every other station in the catalog, so v13 takes both of its paths
+        let stations = parse_output(expected, 3);
+        let catalog: Vec<&[u8]> =
+          stations.iter().step_by(2).map(|(station, _)| station.as_bytes()).collect();
+        let catalog = PerfectHash::new(&catalog);
+        assert_matches(&solve_v13_input(input, &catalog, &names), expected, name);
+      }
+    })
+    .unwrap()
+    .join()
+    .unwrap();
+}
+
+#[test]
+fn v15_matches_samples() {
+  // more than one thread so the chunks get split between them
+  let plan = ThreadPlan {
+    threads: 2,
+    cpus: Vec::new(),
+  };
+  let (names, format) = (NameConfig::default(), InputFormat::default());
+  for (name, input, expected) in samples() {
+    assert_matches(&solve_v15_input(input, &plan, &names, &format), expected, name);
+    assert_matches(&solve_v15_input(&input.to_vec(), &plan, &names, &format), expected, name);
+  }
+}
+
+/// the sample with a csv header and a `#` comment before every third line
+fn with_header_and_comments(input: &[u8]) -> Vec<u8> {
+  let mut result = b"station;temperature\n".to_vec();
+  for (i, line) in input.split_inclusive(|b| *b == b'\n').enumerate() {
+    if i % 3 == 0 {
+      result.extend_from_slice(b"# Hamburg;12.0\n");
+    }
+    result.extend_from_slice(line);
+  }
+  result
+}
+
+/// the sample as a windows tool might write it, with a byte order mark and
+/// whitespace around every name and value
+fn padded(input: &[u8]) -> Vec<u8> {
+  let mut result = b"\xef\xbb\xbf".to_vec();
+  for line in input.split_inclusive(|b| *b == b'\n') {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    let semicolon = line.iter().position(|b| *b == b';').unwrap();
+    result.extend_from_slice(b" ");
+    result.extend_from_slice(&line[..semicolon]);
+    result.extend_from_slice(b"\t; ");
+    result.extend_from_slice(&line[semicolon + 1..]);
+    result.extend_from_slice(b" \r\n");
+  }
+  result
+}
+
+#[test]
+fn solvers_strip_the_byte_order_mark_and_trim() {
+  let plan = ThreadPlan {
+    threads: 2,
+    cpus: Vec::new(),
+  };
+  let names = NameConfig::default();
+  let trim = InputFormat {
+    trim: true,
+    ..Default::default()
+  };
+  for (name, input, expected) in samples() {
+    let with_bom = [b"\xef\xbb\xbf", input].concat();
+    let plain = InputFormat::default();
+    assert_matches(&solve_v10_input(&with_bom, &names, &plain), expected, name);
+    assert_matches(&solve_v15_input(&with_bom, &plan, &names, &plain), expected, name);
+
+    let input = padded(input);
+    assert_matches(&solve_v10_input(&input, &names, &trim), expected, name);
+    assert_matches(&solve_v15_input(&input, &plan, &names, &trim), expected, name);
+  }
+}
+
+#[test]
+fn solvers_read_decimal_commas() {
+  let plan = ThreadPlan {
+    threads: 2,
+    cpus: Vec::new(),
+  };
+  let names = NameConfig::default();
+  let format = InputFormat {
+    number: NumberFormat::new(DecimalSeparator::Comma, Some(b'.')).unwrap(),
+    ..Default::default()
+  };
+  for (name, input, expected) in samples() {
+    // names can have dots too, only the last one on a line is the decimal point
+    let mut input = input.to_vec();
+    for line in input.split_mut(|b| *b == b'\n') {
+      if let Some(point) = line.iter().rposition(|b| *b == b'.') {
+        line[point] = b',';
+      }
+    }
+    assert_matches(&solve_v10_input(&input, &names, &format), expected, name);
+    assert_matches(&solve_v15_input(&input, &plan, &names, &format), expected, name);
+  }
+
+  // more than an i32 sum and an f32 can hold
+  let input = "Hamburg;1.000.000,0\nOslo;12.345.678,9\nOslo;12.345.679,1\n".repeat(300);
+  let expected = "{Hamburg=1000000.0/1000000.0/1000000.0, Oslo=12345678.9/12345679.0/12345679.1}\n";
+  assert_eq!(solve_v10_input(input.as_bytes(), &names, &format), expected);
+  assert_eq!(solve_v15_input(input.as_bytes(), &plan, &names, &format), expected);
+}
+
+#[test]
+fn solvers_skip_headers_and_comments() {
+  let plan = ThreadPlan {
+    threads: 2,
+    cpus: Vec::new(),
+  };
+  let (names, format) = (
+    NameConfig::default(),
+    InputFormat {
+      comment_prefix: Some("#".to_string()),
+      header_lines: 1,
+      ..Default::default()
+    },
+  );
+  for (name, input, expected) in samples() {
+    let input = with_header_and_comments(input);
+    assert_matches(&solve_v10_input(&input, &names, &format), expected, name);
+    assert_matches(&solve_v15_input(&input, &plan, &names, &format), expected, name);
+  }
+}
+
+#[test]
+fn solvers_count_missing_values() {
+  let plan = ThreadPlan {
+    threads: 2,
+    cpus: Vec::new(),
+  };
+  let names = NameConfig::default();
+  let format = InputFormat {
+    missing: vec!["NA".to_string()],
+    sentinels: vec![-9999],
+    ..Default::default()
+  };
+  for (name, input, expected) in samples() {
+    // every line again with a missing value, so a station's missing count is
+    // its number of rows
+    let mut with_missing = Vec::new();
+    let mut rows = HashMap::new();
+    for (i, line) in input.split_inclusive(|b| *b == b'\n').enumerate() {
+      let line = line.strip_suffix(b"\n").unwrap_or(line);
+      let station = &line[..line.iter().position(|b| *b == b';').unwrap()];
+      let missing: &[u8] = if i % 2 == 0 { b";NA\n" } else { b";-999.9\n" };
+      with_missing.extend_from_slice(&[line, b"\n", station, missing].concat());
+      *rows.entry(String::from_utf8(station.to_vec()).unwrap()).or_insert(0) += 1;
+    }
+    for output in [
+      solve_v10_input(&with_missing, &names, &format),
+      solve_v15_input(&with_missing, &plan, &names, &format),
+    ] {
+      let entries = parse_output(&output, 4);
+      for (station, values) in &entries {
+        assert_eq!(values[3], rows[station] as f64, "{} {}", name, station);
+      }
+      let entries: Vec<_> = entries
+        .iter()
+        .map(|(station, values)| format!("{}={}/{}/{}", station, values[0], values[1], values[2]))
+        .collect();
+      assert_matches(&format!("{{{}}}\n", entries.join(", ")), expected, name);
+    }
+  }
+}